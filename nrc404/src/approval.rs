@@ -40,7 +40,7 @@ impl NonFungibleTokenCore for Contract {
     fn nft_approve(&mut self, token_id: TokenId, account_id: AccountId, msg: Option<String>) {
         /*
             assert at least one yocto for security reasons - this will cause a redirect to the NEAR wallet.
-            The user needs to attach enough to pay for storage on the contract
+            The attached deposit goes to the owner's storage balance, which pays for the approval
         */
         assert_at_least_one_yocto();
 
@@ -54,6 +54,9 @@ impl NonFungibleTokenCore for Contract {
             "Predecessor must be the token owner."
         );
 
+        //add the attached deposit to the owner's storage balance
+        self.internal_storage_deposit(&token.owner_id, env::attached_deposit());
+
         //get the next approval ID if we need a new approval
         let approval_id: u64 = token.next_approval_id;
 
@@ -78,8 +81,8 @@ impl NonFungibleTokenCore for Contract {
         //insert the token back into the tokens_by_id collection
         self.tokens_by_id.insert(&token_id, &token);
 
-        //refund any excess storage attached by the user. If the user didn't attach enough, panic. 
-        refund_deposit(storage_used);
+        //charge the storage to the owner's storage balance. If the balance doesn't cover it, panic.
+        self.internal_storage_charge(&token.owner_id, storage_used);
 
         //if some message was passed into the function, we initiate a cross contract call on the
         //account we're giving access to. 
@@ -143,7 +146,7 @@ impl NonFungibleTokenCore for Contract {
             .is_some()
         {
             //refund the funds released by removing the approved_account_id to the caller of the function
-            refund_approved_account_ids_iter(predecessor_account_id, [account_id].iter());
+            self.refund_approved_account_ids_iter(&predecessor_account_id, [account_id].iter());
 
             //insert the token back into the tokens_by_id collection with the account_id removed from the approval list
             self.tokens_by_id.insert(&token_id, &token);
@@ -165,7 +168,7 @@ impl NonFungibleTokenCore for Contract {
         //only revoke if the approved account IDs for the token is not empty
         if !token.approved_account_ids.is_empty() {
             //refund the approved account IDs to the caller of the function
-            refund_approved_account_ids(predecessor_account_id, &token.approved_account_ids);
+            self.refund_approved_account_ids(&predecessor_account_id, &token.approved_account_ids);
             //clear the approved account IDs
             token.approved_account_ids.clear();
             //insert the token back into the tokens_by_id collection with the approved account IDs cleared
@@ -1,4 +1,5 @@
 use crate::*;
+use near_sdk::require;
 
 #[near_bindgen]
 impl Contract {
@@ -74,6 +75,176 @@ impl Contract {
             .collect()
     }
 
+    //get the number of NFTs of a level for a given owner
+    pub fn nrc404_supply_for_owner_by_level(&self, account_id: AccountId, level: u8) -> U128 {
+        require!(!self.internal_levels_hidden(), LEVELS_HIDDEN);
+        let level_tokens = self.level_tokens_per_owner.get(&account_id).and_then(|level_token_set| level_token_set.get(&level));
+        U128(level_tokens.map_or(0, |level_tokens| level_tokens.len() as u128))
+    }
+
+    //Query for the tokens of a level for an owner using pagination
+    pub fn nrc404_tokens_for_owner_by_level(
+        &self,
+        account_id: AccountId,
+        level: u8,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<JsonToken> {
+        require!(!self.internal_levels_hidden(), LEVELS_HIDDEN);
+        let level_tokens = match self.level_tokens_per_owner.get(&account_id).and_then(|level_token_set| level_token_set.get(&level)) {
+            Some(level_tokens) => level_tokens,
+            None => return vec![],
+        };
+
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        level_tokens.iter()
+            //skip to the index we specified in the start variable
+            .skip(start as usize)
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            //we'll map the token IDs which are strings into Json Tokens
+            .map(|token_id| self.nft_token(token_id).unwrap())
+            .collect()
+    }
+
+    //get the number of owned NFTs of a level, tokens in the reserve are not counted
+    pub fn nrc404_supply_by_level(&self, level: u8) -> U128 {
+        self.assert_indexes_backfilled();
+        require!(!self.internal_levels_hidden(), LEVELS_HIDDEN);
+        U128(self.level_supply.get(&level).unwrap_or(0) as u128)
+    }
+
+    //get the supply of every level next to the configured level probability
+    pub fn nrc404_level_distribution(&self) -> Vec<LevelDistribution> {
+        self.assert_indexes_backfilled();
+        require!(!self.internal_levels_hidden(), LEVELS_HIDDEN);
+        let metadata = self.metadata.get().unwrap();
+        let level_probability = metadata.level_probability.unwrap_or_default();
+        let level_supply: Vec<u64> = (1..=metadata.max_level).map(|level| self.level_supply.get(&level).unwrap_or(0)).collect();
+        let revealed_supply: u64 = level_supply.iter().sum();
+        level_supply.iter().enumerate()
+            .map(|(index, supply)| LevelDistribution {
+                level: index as u8 + 1,
+                supply: U128(*supply as u128),
+                observed_rate: (*supply * MAX_LEVEL_PROBABILITY as u64).checked_div(revealed_supply).unwrap_or(0) as u16,
+                expected_rate: level_probability.get(index).copied(),
+            })
+            .collect()
+    }
+
+    //get the supply of every level next to its cap and the room left under it
+    pub fn nrc404_level_capacity(&self) -> Vec<LevelCapacity> {
+        self.assert_indexes_backfilled();
+        require!(!self.internal_levels_hidden(), LEVELS_HIDDEN);
+        let metadata = self.metadata.get().unwrap();
+        (1..=metadata.max_level)
+            .map(|level| {
+                let supply = self.internal_level_supply(level);
+                let cap = Contract::internal_level_cap(&metadata, level);
+                LevelCapacity {
+                    level,
+                    supply: U128(supply as u128),
+                    cap: cap.map(|cap| U128(cap as u128)),
+                    remaining: cap.map(|cap| U128(cap.saturating_sub(supply) as u128)),
+                }
+            })
+            .collect()
+    }
+
+    //get the number of accounts holding FT or NFTs
+    pub fn nrc404_holder_count(&self) -> U128 {
+        self.assert_indexes_backfilled();
+        U128(self.holders.len() as u128)
+    }
+
+    //Query for the accounts holding FT or NFTs using pagination
+    pub fn nrc404_holders(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Holder> {
+        self.assert_indexes_backfilled();
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+        let max_level = self.metadata.get().unwrap().max_level;
+        //the level counts stay empty until the collection is revealed
+        let levels_hidden = self.internal_levels_hidden();
+
+        self.holders.iter()
+            //skip to the index we specified in the start variable
+            .skip(start as usize)
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            .map(|account_id| {
+                let mut level_counts = HashMap::new();
+                if let Some(level_token_set) = self.level_tokens_per_owner.get(&account_id).filter(|_| !levels_hidden) {
+                    for level in PENDING_LEVEL..=max_level {
+                        if let Some(level_tokens) = level_token_set.get(&level) {
+                            level_counts.insert(level, U128(level_tokens.len() as u128));
+                        }
+                    }
+                }
+                Holder {
+                    ft_balance: self.ft_balance_of(account_id.clone()),
+                    nft_count: self.nft_supply_for_owner(account_id.clone()),
+                    level_counts,
+                    account_id,
+                }
+            })
+            .collect()
+    }
+
+    //get the number of NFTs still owed to an account after a wrap ran out of gas
+    pub fn nrc404_pending_mints_of(&self, account_id: AccountId) -> U128 {
+        U128(self.pending_mints.get(&account_id).unwrap_or(0))
+    }
+
+    //Query for all the accounts that are still owed NFTs using pagination
+    pub fn nrc404_pending_mints(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<PendingMint> {
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        self.pending_mints.iter()
+            //skip to the index we specified in the start variable
+            .skip(start as usize)
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            .map(|(account_id, count)| PendingMint { account_id, count: U128(count) })
+            .collect()
+    }
+
+    //get the number of burned tokens waiting in the reserve
+    pub fn nrc404_reserve_supply(&self) -> U128 {
+        U128((self.reserve_tail - self.reserve_head) as u128)
+    }
+
+    //Query for the tokens in the reserve, oldest first, using pagination
+    pub fn nrc404_reserve_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<JsonToken> {
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+        let metadata = self.metadata.get().unwrap();
+        let mediadata = self.mediadata.get().unwrap();
+
+        (self.reserve_head..self.reserve_tail)
+            //skip to the index we specified in the start variable
+            .skip(start as usize)
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            //the reserve is held by the contract itself
+            .map(|slot| {
+                let token_id = self.reserve_token_ids.get(&slot).unwrap();
+                let mut token_metadata = self.reserve_token_metadata.get(&token_id).unwrap();
+                self.internal_render_token_metadata(&metadata, &mediadata, &token_id, &mut token_metadata);
+                token_metadata.denomination = Some(1);
+                JsonToken {
+                    token_id,
+                    owner_id: env::current_account_id(),
+                    metadata: token_metadata,
+                    approved_account_ids: HashMap::new(),
+                    royalty: HashMap::new(),
+                }
+            })
+            .collect()
+    }
+
     pub fn query_protocol_fee(&self) -> U128 {
         return U128::from(self.protocol_fee);
     }
@@ -2,6 +2,34 @@
 pub const LESS_BALANCE: &str = "less balance";
 pub const BALANCE_OVERFLOW: &str = "balance overflow";
 pub const INVALID_LEVEL: &str = "invalid level";
+pub const NFT_LOCKED: &str = "only locked NFTs left to cover the amount";
+pub const TOKEN_LOCKED: &str = "token is locked";
+pub const LEVEL_JUST_REVEALED: &str = "level was revealed in this block, try again in the next one";
+pub const NFT_TO_CONTRACT: &str = "NFTs can only be sent to the contract with nft_transfer_call";
+pub const FUSION_DISABLED: &str = "fusion is disabled for this level";
+pub const INVALID_FUSION_COUNT: &str = "invalid number of tokens to fuse";
+pub const REROLL_DISABLED: &str = "reroll is disabled";
+pub const REROLL_COOLDOWN: &str = "token is still in reroll cooldown";
+pub const STORAGE_INSUFFICIENT: &str = "insufficient storage balance, call storage_deposit first";
+pub const INVALID_PROTOCOL_FEE_RATE: &str = "protocol fee rate is above the maximum";
+pub const INVALID_FEE_SPLITS: &str = "invalid fee splits";
+pub const INVALID_ROYALTY: &str = "invalid royalty";
+pub const NO_FEES_TO_CLAIM: &str = "no fees to claim";
+pub const FT_TRANSFER_PAUSED: &str = "ft transfers are paused";
+pub const NFT_TRANSFER_PAUSED: &str = "nft transfers are paused";
+pub const WRAP_PAUSED: &str = "wrapping is paused";
+pub const OPERATOR_WRAP_PAUSED: &str = "operator wrapping is paused";
 pub const INVALID_LEVEL_INITIAL: &str = "invalid level initial";
+pub const INVALID_UNITS_PER_NFT: &str = "units per NFT must be positive";
+pub const INVALID_DENOMINATIONS: &str = "denominations must be ascending and start at 1";
+pub const ALREADY_REVEALED: &str = "the collection is already revealed";
+pub const LEVELS_HIDDEN: &str = "levels are hidden until the collection is revealed";
+pub const INVALID_LEVEL_DESCRIPTORS: &str = "level descriptors need one entry per level media";
+pub const INVALID_LEVEL_SUPPLY_CAPS: &str = "level supply caps need one entry per level and no cap on the lowest level";
+pub const LEVEL_CAP_REACHED: &str = "level supply cap reached";
+pub const INVALID_DENOMINATION: &str = "denomination is not in the ladder";
+pub const INDEXES_NOT_BACKFILLED: &str = "token indexes are still being backfilled after the migration";
+pub const UNCLAIMED_FEES: &str = "claim the fees in the vault before unregistering";
+pub const ACCOUNT_HAS_ROLES: &str = "renounce the roles before unregistering";
 pub const CAN_NOT_READ_STATE: &str = "CAN_NOT_READ_STATE";
 pub const ERR_NOT_ALLOWED: &str = "ERR_NOT_ALLOWED";
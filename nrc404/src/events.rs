@@ -1,9 +1,11 @@
 use std::fmt;
 use near_sdk::{AccountId, env};
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{Base64VecU8, U128};
 
 use near_sdk::serde::{Deserialize, Serialize};
 
+use crate::{PauseSwitch, Role, NRC404_STANDARD_VERSION};
+
 /// Enum that represents the data type of the EventLog.
 /// The enum can either be an NftMint or an NftTransfer.
 #[derive(Serialize, Deserialize, Debug)]
@@ -15,6 +17,7 @@ pub enum EventLogVariant {
     NftMint(Vec<NftMintLog>),
     NftTransfer(Vec<NftTransferLog>),
     NftBurn(Vec<NftMintLog>),
+    NftMetadataUpdate(Vec<NftMetadataUpdateLog>),
 }
 
 /// Interface to capture data about an event
@@ -80,12 +83,27 @@ pub struct NftTransferLog {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub memo: Option<String>,
 }
+
+/// An event log to capture a change of the token metadata
+///
+/// Arguments
+/// * `token_ids`: ["1", "12345abc"]
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftMetadataUpdateLog {
+    pub token_ids: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
 #[derive(Serialize, Debug)]
 #[serde(tag = "standard")]
 #[must_use = "don't forget to `.emit()` this event"]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum NearEvent<'a> {
     Nep141(Nep141Event<'a>),
+    Nrc404(Nrc404Event<'a>),
 }
 
 impl<'a> NearEvent<'a> {
@@ -131,6 +149,30 @@ impl FtMint<'_> {
     }
 }
 
+/// Data to log for an FT burn event. To log this event, call [`.emit()`](FtBurn::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct FtBurn<'a> {
+    pub owner_id: &'a AccountId,
+    pub amount: &'a U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl FtBurn<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits an FT burn event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`FtBurn`] represents the data of each burn.
+    pub fn emit_many(data: &[FtBurn<'_>]) {
+        new_141_v1(Nep141EventKind::FtBurn(data)).emit()
+    }
+}
+
 /// Data to log for an FT transfer event. To log this event,
 /// call [`.emit()`](FtTransfer::emit).
 #[must_use]
@@ -170,6 +212,7 @@ pub(crate) struct Nep141Event<'a> {
 #[allow(clippy::enum_variant_names)]
 enum Nep141EventKind<'a> {
     FtMint(&'a [FtMint<'a>]),
+    FtBurn(&'a [FtBurn<'a>]),
     FtTransfer(&'a [FtTransfer<'a>]),
 }
 
@@ -181,6 +224,324 @@ fn new_141_v1(event_kind: Nep141EventKind) -> NearEvent {
     new_141("1.0.0", event_kind)
 }
 
+/// Data to log when the level of an auto-minted token is held back until a future block.
+/// To log this event, call [`.emit()`](LevelCommit::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct LevelCommit<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_id: &'a str,
+    pub commit_height: u64,
+    pub reveal_height: u64,
+}
+
+impl LevelCommit<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a level commit event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`LevelCommit`] represents the data of each commitment.
+    pub fn emit_many(data: &[LevelCommit<'_>]) {
+        new_nrc404_v1(Nrc404EventKind::LevelCommit(data)).emit()
+    }
+}
+
+/// Data to log when a committed level is revealed. `random_seed` is the seed of the block the reveal
+/// happened in, the first block at or after `reveal_height` in which the owner was touched, so it can not
+/// be checked against the commitment. The level is the first 8 bytes (little endian) of
+/// `sha256(random_seed || token_id || reveal_height)` modulo `MAX_LEVEL_PROBABILITY` walked through the
+/// configured `level_probability`, lowered when the rolled level is at its supply cap.
+/// To log this event, call [`.emit()`](LevelAssigned::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct LevelAssigned<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_id: &'a str,
+    // left out, together with the seed it can be recomputed from, until the collection is revealed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<u8>,
+    pub reveal_height: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub random_seed: Option<&'a Base64VecU8>,
+}
+
+impl LevelAssigned<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a level assigned event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`LevelAssigned`] represents the data of each revealed level.
+    pub fn emit_many(data: &[LevelAssigned<'_>]) {
+        new_nrc404_v1(Nrc404EventKind::LevelAssigned(data)).emit()
+    }
+}
+
+/// Data to log when FT is wrapped into NFTs. `auto` is false for an explicit `nft_wrap`
+/// and true when the NFTs are minted because the balance reached `units_per_nft`.
+/// To log this event, call [`.emit()`](NftWrap::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct NftWrap<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: &'a [String],
+    pub amount: &'a U128,
+    pub auto: bool,
+}
+
+impl NftWrap<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::NftWrap(&[self])).emit()
+    }
+}
+
+/// Data to log when NFTs are unwrapped back into FT. `auto` is false for an explicit unwrap
+/// and true when the NFTs are burned to cover an FT amount.
+/// To log this event, call [`.emit()`](NftUnwrap::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct NftUnwrap<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: &'a [String],
+    pub amount: &'a U128,
+    pub auto: bool,
+}
+
+impl NftUnwrap<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::NftUnwrap(&[self])).emit()
+    }
+}
+
+/// Data to log when a note standing for more than one NFT unit is minted, ahead of its nep171 nft_mint.
+/// Tokens without this event are single notes. Notes keep their denomination until they are burned.
+/// To log this event, call [`.emit()`](NoteMint::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct NoteMint<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_id: &'a str,
+    pub denomination: u32,
+}
+
+impl NoteMint<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::NoteMint(&[self])).emit()
+    }
+}
+
+/// Data to log when a protocol fee is charged. To log this event, call [`.emit()`](ProtocolFeeCharged::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct ProtocolFeeCharged<'a> {
+    pub sender_id: &'a AccountId,
+    pub amount: &'a U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl ProtocolFeeCharged<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::ProtocolFeeCharged(&[self])).emit()
+    }
+}
+
+/// Data to log when an account is added to or removed from the mint or fee white list.
+/// To log this event, call [`.emit()`](WhitelistUpdated::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct WhitelistUpdated<'a> {
+    pub list: &'a str,
+    pub account_id: &'a AccountId,
+    pub enabled: bool,
+}
+
+impl WhitelistUpdated<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::WhitelistUpdated(&[self])).emit()
+    }
+}
+
+/// Data to log when the FT icon and level medias are updated. To log this event, call [`.emit()`](MediaUpdated::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct MediaUpdated<'a> {
+    pub sender_id: &'a AccountId,
+}
+
+impl MediaUpdated<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::MediaUpdated(&[self])).emit()
+    }
+}
+
+/// Data to log when the operator is changed. To log this event, call [`.emit()`](OperatorChanged::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct OperatorChanged<'a> {
+    pub old_operator_id: &'a AccountId,
+    pub new_operator_id: &'a AccountId,
+}
+
+impl OperatorChanged<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::OperatorChanged(&[self])).emit()
+    }
+}
+
+/// Data to log when a role is granted. To log this event, call [`.emit()`](RoleGranted::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct RoleGranted<'a> {
+    pub account_id: &'a AccountId,
+    pub role: Role,
+    pub sender_id: &'a AccountId,
+}
+
+impl RoleGranted<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::RoleGranted(&[self])).emit()
+    }
+}
+
+/// Data to log when a role is revoked or renounced. To log this event, call [`.emit()`](RoleRevoked::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct RoleRevoked<'a> {
+    pub account_id: &'a AccountId,
+    pub role: Role,
+    pub sender_id: &'a AccountId,
+}
+
+impl RoleRevoked<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::RoleRevoked(&[self])).emit()
+    }
+}
+
+/// Data to log when a new owner is proposed. To log this event, call [`.emit()`](OwnershipProposed::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct OwnershipProposed<'a> {
+    pub owner_id: &'a AccountId,
+    pub pending_owner_id: &'a AccountId,
+}
+
+impl OwnershipProposed<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::OwnershipProposed(&[self])).emit()
+    }
+}
+
+/// Data to log when the proposed owner accepts the ownership. To log this event, call [`.emit()`](OwnershipTransferred::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct OwnershipTransferred<'a> {
+    pub old_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+}
+
+impl OwnershipTransferred<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::OwnershipTransferred(&[self])).emit()
+    }
+}
+
+/// Data to log when switches are paused. To log this event, call [`.emit()`](Paused::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct Paused<'a> {
+    pub account_id: &'a AccountId,
+    pub switches: &'a [PauseSwitch],
+}
+
+impl Paused<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::Paused(&[self])).emit()
+    }
+}
+
+/// Data to log when switches are unpaused. To log this event, call [`.emit()`](Unpaused::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct Unpaused<'a> {
+    pub account_id: &'a AccountId,
+    pub switches: &'a [PauseSwitch],
+}
+
+impl Unpaused<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        new_nrc404_v1(Nrc404EventKind::Unpaused(&[self])).emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Nrc404Event<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: Nrc404EventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+enum Nrc404EventKind<'a> {
+    LevelCommit(&'a [LevelCommit<'a>]),
+    LevelAssigned(&'a [LevelAssigned<'a>]),
+    RoleGranted(&'a [RoleGranted<'a>]),
+    RoleRevoked(&'a [RoleRevoked<'a>]),
+    OwnershipProposed(&'a [OwnershipProposed<'a>]),
+    OwnershipTransferred(&'a [OwnershipTransferred<'a>]),
+    Paused(&'a [Paused<'a>]),
+    Unpaused(&'a [Unpaused<'a>]),
+    NftWrap(&'a [NftWrap<'a>]),
+    NftUnwrap(&'a [NftUnwrap<'a>]),
+    NoteMint(&'a [NoteMint<'a>]),
+    ProtocolFeeCharged(&'a [ProtocolFeeCharged<'a>]),
+    WhitelistUpdated(&'a [WhitelistUpdated<'a>]),
+    MediaUpdated(&'a [MediaUpdated<'a>]),
+    OperatorChanged(&'a [OperatorChanged<'a>]),
+}
+
+fn new_nrc404<'a>(version: &'static str, event_kind: Nrc404EventKind<'a>) -> NearEvent<'a> {
+    NearEvent::Nrc404(Nrc404Event { version, event_kind })
+}
+
+fn new_nrc404_v1(event_kind: Nrc404EventKind) -> NearEvent {
+    new_nrc404(NRC404_STANDARD_VERSION, event_kind)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -222,6 +583,20 @@ mod tests {
         assert_eq!(expected, log.to_string());
     }
 
+    #[test]
+    fn nep_format_metadata_update() {
+        let expected = r#"EVENT_JSON:{"standard":"nep171","version":"1.1.0","event":"nft_metadata_update","data":[{"token_ids":["0","1"],"memo":"reveal"}]}"#;
+        let log = EventLog {
+            standard: "nep171".to_string(),
+            version: "1.1.0".to_string(),
+            event: EventLogVariant::NftMetadataUpdate(vec![NftMetadataUpdateLog {
+                token_ids: vec!["0".to_string(), "1".to_string()],
+                memo: Some("reveal".to_string()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
     #[test]
     fn nep_format_transfer_all_fields() {
         let expected = r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"authorized_id":"market.near","old_owner_id":"user1.near","new_owner_id":"user2.near","token_ids":["token"],"memo":"Go Team!"}]}"#;
@@ -238,4 +613,47 @@ mod tests {
         };
         assert_eq!(expected, log.to_string());
     }
+
+    #[test]
+    fn nrc404_format_wrap() {
+        let expected = r#"EVENT_JSON:{"standard":"nrc404","version":"1.0.0","event":"nft_wrap","data":[{"owner_id":"user1.near","token_ids":["1","2"],"amount":"2000000000000000000000000","auto":true}]}"#;
+        let owner_id: AccountId = "user1.near".parse().unwrap();
+        let token_ids = vec!["1".to_string(), "2".to_string()];
+        let amount = U128(2_000_000_000_000_000_000_000_000);
+        let data = [NftWrap {
+            owner_id: &owner_id,
+            token_ids: &token_ids,
+            amount: &amount,
+            auto: true,
+        }];
+        let event = new_nrc404_v1(Nrc404EventKind::NftWrap(&data));
+        assert_eq!(expected, event.to_json_event_string());
+    }
+
+    #[test]
+    fn nrc404_format_note_mint() {
+        let expected = r#"EVENT_JSON:{"standard":"nrc404","version":"1.0.0","event":"note_mint","data":[{"owner_id":"user1.near","token_id":"7","denomination":10}]}"#;
+        let owner_id: AccountId = "user1.near".parse().unwrap();
+        let data = [NoteMint {
+            owner_id: &owner_id,
+            token_id: "7",
+            denomination: 10,
+        }];
+        let event = new_nrc404_v1(Nrc404EventKind::NoteMint(&data));
+        assert_eq!(expected, event.to_json_event_string());
+    }
+
+    #[test]
+    fn nrc404_format_protocol_fee_charged() {
+        let expected = r#"EVENT_JSON:{"standard":"nrc404","version":"1.0.0","event":"protocol_fee_charged","data":[{"sender_id":"user1.near","amount":"100","memo":"Protocol fee"}]}"#;
+        let sender_id: AccountId = "user1.near".parse().unwrap();
+        let amount = U128(100);
+        let data = [ProtocolFeeCharged {
+            sender_id: &sender_id,
+            amount: &amount,
+            memo: Some("Protocol fee"),
+        }];
+        let event = new_nrc404_v1(Nrc404EventKind::ProtocolFeeCharged(&data));
+        assert_eq!(expected, event.to_json_event_string());
+    }
 }
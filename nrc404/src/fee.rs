@@ -0,0 +1,81 @@
+use near_sdk::require;
+use crate::*;
+
+impl Contract {
+    /// Move a fee from the sender into the vault held by the contract account and split it between the recipients.
+    pub(crate) fn internal_collect_fee(&mut self, sender_id: &AccountId, fee: Balance, memo: &str) {
+        self.protocol_fee += fee;
+        self.internal_transfer_ft(sender_id, &env::current_account_id(), fee, Some(memo.to_string()));
+        ProtocolFeeCharged {
+            sender_id,
+            amount: &U128(fee),
+            memo: Some(memo),
+        }.emit();
+
+        let mut distributed = 0;
+        for fee_split in self.fee_splits.clone().iter() {
+            let share = fee * fee_split.share as u128 / FEE_SPLIT_DENOMINATOR as u128;
+            let recipient_id = match &fee_split.recipient {
+                FeeRecipient::Treasury => self.treasury_id.clone(),
+                FeeRecipient::Referrer => self.referrers.get(sender_id).unwrap_or_else(|| self.treasury_id.clone()),
+                FeeRecipient::Account(account_id) => account_id.clone(),
+            };
+            self.internal_add_claimable_fee(&recipient_id, share);
+            distributed += share;
+        }
+        // rounding dust goes to the treasury
+        self.internal_add_claimable_fee(&self.treasury_id.clone(), fee - distributed);
+    }
+
+    fn internal_add_claimable_fee(&mut self, account_id: &AccountId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+        let claimable = self.fee_vault.get(account_id).unwrap_or(0);
+        self.fee_vault.insert(account_id, &(claimable + amount));
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Claim the protocol fees the caller is owed. They are moved from the contract account to the caller's balance.
+    #[payable]
+    pub fn nrc404_claim_fees(&mut self) -> U128 {
+        assert_one_yocto();
+        // claiming moves FT out of the vault, so it stops with the FT transfers
+        self.assert_not_paused(PauseSwitch::FtTransfer);
+        let account_id = env::predecessor_account_id();
+        let claimable = self.fee_vault.remove(&account_id).unwrap_or(0);
+        require!(claimable > 0, NO_FEES_TO_CLAIM);
+        self.internal_transfer_ft(&env::current_account_id(), &account_id, claimable, Some("Fee claim".to_string()));
+        self.internal_wrap_ft_to_nft(&account_id);
+        U128(claimable)
+    }
+
+    /// Set the account receiving the referrer share of the fees paid by the caller. It can only be set once.
+    #[payable]
+    pub fn nrc404_set_referrer(&mut self, referrer_id: AccountId) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        require!(referrer_id != account_id, "Can't refer yourself");
+        require!(!self.referrers.contains_key(&account_id), "Referrer already set");
+        self.referrers.insert(&account_id, &referrer_id);
+    }
+
+    pub fn nrc404_referrer_of(&self, account_id: AccountId) -> Option<AccountId> {
+        self.referrers.get(&account_id)
+    }
+
+    /// Get the protocol fees the account can claim.
+    pub fn nrc404_claimable_fees(&self, account_id: AccountId) -> U128 {
+        U128(self.fee_vault.get(&account_id).unwrap_or(0))
+    }
+
+    pub fn nrc404_fee_config(&self) -> FeeConfig {
+        FeeConfig {
+            protocol_fee_rate: U128(self.protocol_fee_rate),
+            treasury_id: self.treasury_id.clone(),
+            fee_splits: self.fee_splits.clone(),
+        }
+    }
+}
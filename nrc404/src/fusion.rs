@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+use near_sdk::require;
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Fuse tokens of the same level into one token of the next level.
+    /// The number of tokens is set per level by the owner, the value of all but one of them
+    /// goes back to the caller's FT balance. Returns the ID of the new token.
+    #[payable]
+    pub fn nrc404_fuse(&mut self, token_ids: Vec<TokenId>) -> TokenId {
+        assert_one_yocto();
+        // fusing mints a token, so it stops with the wrapping
+        self.assert_not_paused(PauseSwitch::Wrap);
+        let account_id = env::predecessor_account_id();
+        let metadata = self.metadata.get().unwrap();
+        require!(!self.internal_levels_hidden(), LEVELS_HIDDEN);
+        require!(!token_ids.is_empty(), INVALID_FUSION_COUNT);
+        require!(token_ids.iter().all(|token_id| self.internal_level_settled(token_id)), LEVEL_JUST_REVEALED);
+        let level = self.token_metadata_by_id.get(&token_ids[0]).expect("No token").level;
+        require!(level != PENDING_LEVEL && level < metadata.max_level, INVALID_LEVEL);
+        // burning the inputs doesn't free room in the next level
+        require!(self.internal_level_has_room(&metadata, level + 1), LEVEL_CAP_REACHED);
+        let fusion_cost = self.fusion_costs.get(&level).expect(FUSION_DISABLED);
+        require!(token_ids.len() == fusion_cost as usize, INVALID_FUSION_COUNT);
+        let unique_token_ids: HashSet<&TokenId> = token_ids.iter().collect();
+        require!(unique_token_ids.len() == token_ids.len(), INVALID_FUSION_COUNT);
+
+        let mut fused_units = 0;
+        for token_id in token_ids.iter() {
+            let token = self.tokens_by_id.get(token_id).expect("No token");
+            require!(token.owner_id == account_id, "Predecessor must be the token owner.");
+            require!(self.token_metadata_by_id.get(token_id).unwrap().level == level, INVALID_LEVEL);
+            fused_units += self.internal_token_denomination(token_id) as u128;
+            self.internal_burn_token(&account_id, token_id, Some(FUSION_MEMO.to_string()));
+        }
+
+        // the fused tokens go back to FT, the new token then costs one unit out of it
+        let units_per_nft = metadata.units_per_nft.0;
+        let fused_amount = U128(fused_units * units_per_nft);
+        self.internal_deposit(&account_id, fused_amount.0);
+        FtMint {
+            owner_id: &account_id,
+            amount: &fused_amount,
+            memo: Some(NFT_TO_FT_MEMO),
+        }.emit();
+        // the surplus stays fungible instead of being wrapped again on the next balance change
+        let kept = self.kept_ft_units.get(&account_id).unwrap_or(0);
+        self.internal_set_kept_ft_units(&account_id, kept + fused_units - 1);
+        let token_metadata = TokenMetadata {
+            level: level + 1, title: None, description: None,
+            media: None,
+            media_hash: None, copies: None, issued_at: Some(env::block_timestamp_ms()), expires_at: None,
+            starts_at: None, updated_at: Some(env::block_timestamp_ms()), extra: None, reference: None, reference_hash: None,
+            denomination: None,
+        };
+        self.internal_mint(account_id.clone(), token_metadata, account_id, None)
+    }
+
+    /// Get how many tokens of `level` are fused into one token of the next level.
+    pub fn nrc404_fusion_cost(&self, level: u8) -> Option<u32> {
+        self.fusion_costs.get(&level)
+    }
+}
@@ -1,10 +1,16 @@
 use crate::*;
-use near_sdk::{CryptoHash};
+use near_sdk::{require, CryptoHash};
 use std::mem::size_of;
 
+//make sure the royalty shares add up to at most 100%, the owner of the token gets the rest
+pub(crate) fn assert_valid_royalty(royalty: &HashMap<AccountId, u32>) {
+    let total = royalty.values().try_fold(0u32, |total, share| total.checked_add(*share));
+    require!(total.is_some_and(|total| total <= ROYALTY_DENOMINATOR), INVALID_ROYALTY);
+}
+
 //convert the royalty percentage and amount to pay into a payout (U128)
 pub(crate) fn royalty_to_payout(royalty_percentage: u32, amount_to_pay: Balance) -> U128 {
-    U128(royalty_percentage as u128 * amount_to_pay / 10_000u128)
+    U128(royalty_percentage as u128 * amount_to_pay / ROYALTY_DENOMINATOR as u128)
 }
 
 //calculate how many bytes the account ID is taking up
@@ -13,29 +19,6 @@ pub(crate) fn bytes_for_approved_account_id(account_id: &AccountId) -> u64 {
     account_id.as_str().len() as u64 + 4 + size_of::<u64>() as u64
 }
 
-//refund the storage taken up by passed in approved account IDs and send the funds to the passed in account ID.
-pub(crate) fn refund_approved_account_ids_iter<'a, I>(
-    account_id: AccountId,
-    approved_account_ids: I, //the approved account IDs must be passed in as an iterator
-) -> Promise
-where
-    I: Iterator<Item = &'a AccountId>,
-{
-    //get the storage total by going through and summing all the bytes for each approved account IDs
-    let storage_released: u64 = approved_account_ids.map(bytes_for_approved_account_id).sum();
-    //transfer the account the storage that is released
-    Promise::new(account_id).transfer(Balance::from(storage_released) * env::storage_byte_cost())
-}
-
-//refund a map of approved account IDs and send the funds to the passed in account ID
-pub(crate) fn refund_approved_account_ids(
-    account_id: AccountId,
-    approved_account_ids: &HashMap<AccountId, u64>,
-) -> Promise {
-    //call the refund_approved_account_ids_iter with the approved account IDs as keys
-    refund_approved_account_ids_iter(account_id, approved_account_ids.keys())
-}
-
 //used to generate a unique prefix in our storage collections (this is to avoid data collisions)
 pub(crate) fn hash_account_id(account_id: &AccountId) -> CryptoHash {
     //get the default hash
@@ -75,30 +58,31 @@ pub(crate) fn assert_at_least_one_yocto() {
     )
 }
 
-//refund the initial deposit based on the amount of storage that was used up
-pub(crate) fn refund_deposit(storage_used: u64) {
-    //get how much it would cost to store the information
-    let required_cost = env::storage_byte_cost() * Balance::from(storage_used);
-    //get the attached deposit
-    let attached_deposit = env::attached_deposit();
-
-    //make sure that the attached deposit is greater than or equal to the required cost
-    assert!(
-        required_cost <= attached_deposit,
-        "Must attach {} yoctoNEAR to cover storage",
-        required_cost,
-    );
-
-    //get the refund amount from the attached deposit - required cost
-    let refund = attached_deposit - required_cost;
+impl Contract {
+    //credit the storage taken up by passed in approved account IDs back to the storage balance of the passed in account ID.
+    pub(crate) fn refund_approved_account_ids_iter<'a, I>(
+        &mut self,
+        account_id: &AccountId,
+        approved_account_ids: I, //the approved account IDs must be passed in as an iterator
+    ) where
+        I: Iterator<Item = &'a AccountId>,
+    {
+        //get the storage total by going through and summing all the bytes for each approved account IDs
+        let storage_released: u64 = approved_account_ids.map(bytes_for_approved_account_id).sum();
+        //give the account back the storage that is released
+        self.internal_storage_release(account_id, storage_released);
+    }
 
-    //if the refund is greater than 1 yocto NEAR, we refund the predecessor that amount
-    if refund > 1 {
-        Promise::new(env::predecessor_account_id()).transfer(refund);
+    //refund a map of approved account IDs to the storage balance of the passed in account ID
+    pub(crate) fn refund_approved_account_ids(
+        &mut self,
+        account_id: &AccountId,
+        approved_account_ids: &HashMap<AccountId, u64>,
+    ) {
+        //call the refund_approved_account_ids_iter with the approved account IDs as keys
+        self.refund_approved_account_ids_iter(account_id, approved_account_ids.keys())
     }
-}
 
-impl Contract {
     //add a token to the set of tokens an owner has
     pub(crate) fn internal_add_token_to_owner(
         &mut self,
@@ -106,6 +90,7 @@ impl Contract {
         token_id: &TokenId,
         level: &u8,
     ) {
+        self.assert_indexes_backfilled();
         //get the set of tokens for the given account
         let mut tokens_set = self.tokens_per_owner.get(account_id).unwrap_or_else(|| {
             //if the account doesn't have any tokens, we create a new unordered set
@@ -123,24 +108,16 @@ impl Contract {
         //we insert that set for the given account ID.
         self.tokens_per_owner.insert(account_id, &tokens_set);
 
-        if !self.level_tokens_per_owner.contains_key(account_id) {
-            self.level_tokens_per_owner.insert(account_id, &LookupMap::new(StorageKey::LevelTokensPerOwnerLevel {
-                account_id_hash: hash_account_id(&account_id),
-            }.try_to_vec().unwrap()));
-        }
-        let mut level_tokens_set = self.level_tokens_per_owner.get(account_id).unwrap();
-        if !level_tokens_set.contains_key(level) {
-            level_tokens_set.insert(level, &UnorderedSet::new(
-                StorageKey::LevelTokensPerOwnerLevelInner {
-                    //we get a new unique prefix for the collection
-                    account_id_level_hash: hash_account_id_level(&account_id, level),
-                }.try_to_vec().unwrap(),
-            ));
+        self.internal_add_token_to_level(account_id, token_id, level);
+        self.internal_add_token_to_receive_order(account_id, token_id);
+
+        let extra_units = self.internal_token_denomination(token_id) as u128 - 1;
+        if extra_units > 0 {
+            let note_extra_units = self.note_extra_units.get(account_id).unwrap_or(0);
+            self.note_extra_units.insert(account_id, &(note_extra_units + extra_units));
         }
-        let mut level_tokens = level_tokens_set.get(level).unwrap();
-        level_tokens.insert(token_id);
-        level_tokens_set.insert(level, &level_tokens);
-        self.level_tokens_per_owner.insert(account_id, &level_tokens_set);
+
+        self.holders.insert(account_id);
     }
 
     //remove a token from an owner (internal method and can't be called directly via CLI).
@@ -150,6 +127,7 @@ impl Contract {
         token_id: &TokenId,
         level: &u8,
     ) {
+        self.assert_indexes_backfilled();
         //we get the set of tokens that the owner has
         let mut tokens_set = self
             .tokens_per_owner
@@ -168,6 +146,79 @@ impl Contract {
             self.tokens_per_owner.insert(account_id, &tokens_set);
         }
 
+        self.internal_remove_token_from_level(account_id, token_id, level);
+
+        //take the token out of the receive order of the owner, tokens from before the order was kept have none
+        if let Some(receive_seq) = self.token_receive_seq.remove(token_id) {
+            let mut receive_order = self.tokens_by_receive_order.get(account_id).expect("Token should be owned by the sender");
+            receive_order.remove(&receive_seq);
+            if receive_order.is_empty() {
+                self.tokens_by_receive_order.remove(account_id);
+            } else {
+                self.tokens_by_receive_order.insert(account_id, &receive_order);
+            }
+        }
+
+        let extra_units = self.internal_token_denomination(token_id) as u128 - 1;
+        if extra_units > 0 {
+            let note_extra_units = self.note_extra_units.get(account_id).unwrap_or(0) - extra_units;
+            if note_extra_units == 0 {
+                self.note_extra_units.remove(account_id);
+            } else {
+                self.note_extra_units.insert(account_id, &note_extra_units);
+            }
+        }
+
+        self.internal_update_holder(account_id);
+    }
+
+    //move a token of an owner to another level, it keeps its place in the receive order
+    pub(crate) fn internal_move_token_level(&mut self, account_id: &AccountId, token_id: &TokenId, from_level: &u8, to_level: &u8) {
+        self.assert_indexes_backfilled();
+        self.internal_remove_token_from_level(account_id, token_id, from_level);
+        self.internal_add_token_to_level(account_id, token_id, to_level);
+    }
+
+    //append a token to the owner's tokens in the order it got them
+    pub(crate) fn internal_add_token_to_receive_order(&mut self, account_id: &AccountId, token_id: &TokenId) {
+        let mut receive_order = self.tokens_by_receive_order.get(account_id).unwrap_or_else(|| {
+            TreeMap::new(
+                StorageKey::TokensByReceiveOrderInner {
+                    account_id_hash: hash_account_id(account_id),
+                }.try_to_vec().unwrap(),
+            )
+        });
+        receive_order.insert(&self.next_receive_seq, token_id);
+        self.tokens_by_receive_order.insert(account_id, &receive_order);
+        self.token_receive_seq.insert(token_id, &self.next_receive_seq);
+        self.next_receive_seq += 1;
+    }
+
+    fn internal_add_token_to_level(&mut self, account_id: &AccountId, token_id: &TokenId, level: &u8) {
+        if !self.level_tokens_per_owner.contains_key(account_id) {
+            self.level_tokens_per_owner.insert(account_id, &LookupMap::new(StorageKey::LevelTokensPerOwnerLevel {
+                account_id_hash: hash_account_id(&account_id),
+            }.try_to_vec().unwrap()));
+        }
+        let mut level_tokens_set = self.level_tokens_per_owner.get(account_id).unwrap();
+        if !level_tokens_set.contains_key(level) {
+            level_tokens_set.insert(level, &UnorderedSet::new(
+                StorageKey::LevelTokensPerOwnerLevelInner {
+                    //we get a new unique prefix for the collection
+                    account_id_level_hash: hash_account_id_level(&account_id, level),
+                }.try_to_vec().unwrap(),
+            ));
+        }
+        let mut level_tokens = level_tokens_set.get(level).unwrap();
+        level_tokens.insert(token_id);
+        level_tokens_set.insert(level, &level_tokens);
+        self.level_tokens_per_owner.insert(account_id, &level_tokens_set);
+
+        let level_supply = self.level_supply.get(level).unwrap_or(0);
+        self.level_supply.insert(level, &(level_supply + 1));
+    }
+
+    fn internal_remove_token_from_level(&mut self, account_id: &AccountId, token_id: &TokenId, level: &u8) {
         let mut level_tokens_set = self
             .level_tokens_per_owner
             .get(account_id)
@@ -183,6 +234,81 @@ impl Contract {
             level_tokens_set.insert(level, &level_tokens);
         }
         self.level_tokens_per_owner.insert(account_id, &level_tokens_set);
+
+        let level_supply = self.level_supply.get(level).unwrap_or(0);
+        if level_supply <= 1 {
+            self.level_supply.remove(level);
+        } else {
+            self.level_supply.insert(level, &(level_supply - 1));
+        }
+    }
+
+    //get the NFT units a token stands for, 1 unless it is a larger note
+    pub(crate) fn internal_token_denomination(&self, token_id: &TokenId) -> u32 {
+        self.token_denominations.get(token_id).unwrap_or(1)
+    }
+
+    //get the NFT units held by an account across all of its notes
+    pub(crate) fn internal_nft_units_of(&self, account_id: &AccountId) -> u128 {
+        let nft_count = self.tokens_per_owner.get(account_id).map_or(0, |tokens| tokens.len() as u128);
+        nft_count + self.note_extra_units.get(account_id).unwrap_or(0)
+    }
+
+    //the level the royalty of a token goes by, hidden levels and a level revealed in this block don't count yet
+    pub(crate) fn internal_royalty_level(&self, token_id: &TokenId) -> u8 {
+        if self.internal_levels_hidden() || !self.internal_level_settled(token_id) {
+            return PENDING_LEVEL;
+        }
+        self.token_metadata_by_id.get(token_id).expect("No token").level
+    }
+
+    //the royalty a token pays out: its own if it was minted with one, otherwise the default of its level or of the collection
+    pub(crate) fn internal_effective_royalty(&self, royalty: &HashMap<AccountId, u32>, level: u8) -> HashMap<AccountId, u32> {
+        let royalty = if royalty.is_empty() {
+            let royalty = self.level_royalties.get(&level).unwrap_or_else(|| self.default_royalty.clone());
+            match self.level_royalty_rates.get(&level) {
+                Some(rate) => self.internal_rescale_royalty(royalty, rate),
+                None => royalty,
+            }
+        } else {
+            royalty.clone()
+        };
+        //recipients that moved their share are paid on their new account
+        let mut effective_royalty: HashMap<AccountId, u32> = HashMap::new();
+        for (account_id, share) in royalty {
+            let recipient_id = self.royalty_recipients.get(&account_id).unwrap_or(account_id);
+            *effective_royalty.entry(recipient_id).or_insert(0) += share;
+        }
+        effective_royalty
+    }
+
+    //scale the shares so they add up to `rate`, keeping the split between the recipients. Without recipients the treasury gets it all.
+    fn internal_rescale_royalty(&self, royalty: HashMap<AccountId, u32>, rate: u32) -> HashMap<AccountId, u32> {
+        let total: u64 = royalty.values().map(|share| *share as u64).sum();
+        if total == 0 {
+            return HashMap::from([(self.treasury_id.clone(), rate)]);
+        }
+        let mut recipients: Vec<(AccountId, u32)> = royalty.into_iter().collect();
+        recipients.sort();
+        let mut rescaled = HashMap::new();
+        let mut distributed = 0;
+        for (account_id, share) in recipients.iter() {
+            let rescaled_share = (*share as u64 * rate as u64 / total) as u32;
+            rescaled.insert(account_id.clone(), rescaled_share);
+            distributed += rescaled_share;
+        }
+        //rounding dust goes to the first recipient
+        *rescaled.get_mut(&recipients[0].0).unwrap() += rate - distributed;
+        rescaled
+    }
+
+    //keep the holder index in sync with the FT balance and the NFTs of an account
+    pub(crate) fn internal_update_holder(&mut self, account_id: &AccountId) {
+        if self.accounts.get(account_id).unwrap_or(0) > 0 || self.tokens_per_owner.contains_key(account_id) {
+            self.holders.insert(account_id);
+        } else {
+            self.holders.remove(account_id);
+        }
     }
 
     //transfers the NFT to the receiver_id (internal method and can't be called directly via CLI).
@@ -195,6 +321,7 @@ impl Contract {
         approval_id: Option<u64>,
         memo: Option<String>,
     ) -> Token {
+        self.assert_not_paused(PauseSwitch::NftTransfer);
         //get the token object by passing in the token_id
         let token = self.tokens_by_id.get(token_id).expect("No token");
         let token_meta = self.token_metadata_by_id.get(token_id).expect("No token");
@@ -234,6 +361,9 @@ impl Contract {
         self.internal_remove_token_from_owner(&token.owner_id, token_id, &token_meta.level);
         //we then add the token to the receiver_id's set
         self.internal_add_token_to_owner(receiver_id, token_id, &token_meta.level);
+        //the storage of the token moves from the sender's storage balance to the receiver's
+        self.internal_storage_release(&token.owner_id, self.bytes_per_nft);
+        self.internal_storage_charge(receiver_id, self.bytes_per_nft);
 
         //we create a new token struct
         let new_token = Token {
@@ -246,6 +376,8 @@ impl Contract {
         };
         //insert that new token into the tokens_by_id, replacing the old entry
         self.tokens_by_id.insert(token_id, &new_token);
+        //a lock only protects the token for the owner that set it
+        self.locked_tokens.remove(token_id);
 
         //if there was some memo attached, we log it.
         if let Some(memo) = memo.as_ref() {
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::collections::{LazyOption, LookupMap, TreeMap, UnorderedMap, UnorderedSet};
 use near_sdk::json_types::{Base64VecU8, U128};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{env, near_bindgen, AccountId, Balance, CryptoHash, PanicOnDefault, Promise, PromiseOrValue, StorageUsage, Gas};
@@ -19,6 +19,7 @@ pub use crate::nep141_metadata::*;
 pub use crate::nep141_storage::*;
 pub use crate::nrc404_internal::*;
 pub use crate::owner::*;
+pub use crate::pause::*;
 
 mod internal;
 mod approval;
@@ -35,31 +36,107 @@ mod nep141_metadata;
 mod nep141_storage;
 mod nrc404_internal;
 mod owner;
+mod lock;
+mod fusion;
+mod reroll;
+mod fee;
+mod pause;
 
 /// This spec can be treated like a version of the standard.
 pub const NFT_METADATA_SPEC: &str = "1.0.0";
 /// This is the name of the NFT standard we're using
 pub const NFT_STANDARD_NAME: &str = "nep171";
+/// Version of the NFT standard that introduced the nft_metadata_update event
+pub const NFT_METADATA_UPDATE_SPEC: &str = "1.1.0";
+/// Max number of token IDs in one nft_metadata_update event
+pub const MAX_METADATA_UPDATE_TOKENS: u128 = 500;
+/// Memo of the nft_metadata_update events logged when the collection is revealed
+pub const REVEAL_MEMO: &str = "reveal";
+/// Version of the nrc404 event standard, bumped when the shape of an event changes
+pub const NRC404_STANDARD_VERSION: &str = "1.0.0";
 pub const MAX_LEVEL_PROBABILITY: u16 = 10000;
 pub const DEFAULT_LEVEL: u8 = 1;
+/// Level of an auto-minted token whose random level has been committed but not yet revealed
+pub const PENDING_LEVEL: u8 = 0;
+/// How many blocks after the wrap the random seed used to reveal a level may be taken from
+pub const LEVEL_REVEAL_DELAY_BLOCKS: u64 = 1;
+/// Max number of pending levels revealed as a side effect of touching an account
+pub const MAX_LEVEL_REVEALS_PER_TOUCH: u64 = 10;
 pub const MAX_RESERVED_WRAP_GAS: Gas = Gas(Gas::ONE_TERA.0 * 5);
 pub const DEFAULT_PROTOCOL_FEE: u128 = 500;
 pub const PROTOCOL_FEE_DENOMINATOR: u128 = 1000000;
+/// Hard cap of the protocol fee rate (5%)
+pub const MAX_PROTOCOL_FEE_RATE: u128 = 50000;
+/// Fee split shares are expressed in basis points
+pub const FEE_SPLIT_DENOMINATOR: u16 = 10000;
+/// Royalty shares are in basis points out of this
+pub const ROYALTY_DENOMINATOR: u32 = 10000;
+/// Highest royalty rate the owner can set for a level, 50%
+pub const MAX_LEVEL_ROYALTY_RATE: u32 = 5000;
+pub const MAX_FEE_SPLITS: usize = 10;
+/// Memo of the nft_burn event logged when a token goes into the reserve
+pub const RESERVE_DEPOSIT_MEMO: &str = "reserve_deposit";
+/// Memo of the nft_mint event logged when a token leaves the reserve
+pub const RESERVE_WITHDRAW_MEMO: &str = "reserve_withdraw";
+/// Memo of the nft_burn event logged when a note larger than one unit is broken back into FT
+pub const NOTE_BREAK_MEMO: &str = "note_break";
+/// Memo of the nft_burn event logged for the tokens consumed by a fusion
+pub const FUSION_MEMO: &str = "fusion";
+/// Memo of the ft_burn emitted when `units_per_nft` of FT is consumed into an NFT
+pub const FT_TO_NFT_MEMO: &str = "ft_to_nft";
+/// Memo of the ft_mint emitted when NFTs are turned back into FT
+pub const NFT_TO_FT_MEMO: &str = "nft_to_ft";
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
     //contract owner
     pub owner_id: AccountId,
+    //account proposed as the next owner, it becomes the owner once it accepts
+    pub pending_owner_id: Option<AccountId>,
     //contract operator
     pub operator: AccountId,
+    /// roles[address] = roles granted to the account
+    pub roles: UnorderedMap<AccountId, Vec<Role>>,
     pub protocol_fee: u128,
     pub protocol_fee_rate: u128,
+    /// account receiving the treasury share of the protocol fees
+    pub treasury_id: AccountId,
+    /// how the protocol fees are split between recipients, shares add up to FEE_SPLIT_DENOMINATOR
+    pub fee_splits: Vec<FeeSplit>,
+    /// fee_vault[address] = protocol fees the account can claim, held by the contract account
+    pub fee_vault: LookupMap<AccountId, Balance>,
+    /// referrers[address] = account receiving the referrer share of the fees paid by address
+    pub referrers: LookupMap<AccountId, AccountId>,
 
     //keeps track of all the token IDs for a given account
     pub tokens_per_owner: LookupMap<AccountId, UnorderedSet<TokenId>>,
     /// level_tokens_per_owner[user][level] = tokenIds
     pub level_tokens_per_owner: LookupMap<AccountId, LookupMap<u8, UnorderedSet<TokenId>>>,
+    /// tokens_by_receive_order[user][receive_seq] = tokenId, in the order the owner got its tokens
+    pub tokens_by_receive_order: LookupMap<AccountId, TreeMap<u64, TokenId>>,
+    /// token_receive_seq[token_id] = key of the token in the receive order of its owner
+    pub token_receive_seq: LookupMap<TokenId, u64>,
+    /// receive_seq given to the next token an account gets
+    pub next_receive_seq: u64,
+    /// level_supply[level] = number of owned tokens of the level, the reserve is not counted
+    pub level_supply: LookupMap<u8, u64>,
+    /// reserve_level_supply[level] = number of tokens of the level waiting in the reserve
+    pub reserve_level_supply: LookupMap<u8, u64>,
+    /// accounts holding FT or NFTs
+    pub holders: UnorderedSet<AccountId>,
+    //royalty paid by tokens minted without one
+    pub default_royalty: HashMap<AccountId, u32>,
+    /// level_royalties[level] = royalty paid by tokens of the level minted without one, overrides default_royalty
+    pub level_royalties: LookupMap<u8, HashMap<AccountId, u32>>,
+    /// royalty_recipients[address] = account the royalty share of address is paid to
+    pub royalty_recipients: LookupMap<AccountId, AccountId>,
+    /// level_royalty_rates[level] = total royalty in basis points paid by tokens of the level minted without one
+    pub level_royalty_rates: LookupMap<u8, u32>,
+    /// token_denominations[token_id] = NFT units of a note larger than one, single notes have no entry
+    pub token_denominations: LookupMap<TokenId, u32>,
+    /// note_extra_units[address] = NFT units held in notes beyond one per token
+    pub note_extra_units: LookupMap<AccountId, u128>,
 
     //keeps track of the token struct for a given token ID
     pub tokens_by_id: LookupMap<TokenId, Token>,
@@ -70,6 +147,8 @@ pub struct Contract {
     //keeps track of the metadata for the contract
     pub metadata: LazyOption<NFTContractMetadata>,
     pub mediadata: LazyOption<NFTMediaData>,
+    //whether the collection was revealed ahead of or at `reveal_at`
+    pub metadata_revealed: bool,
 
     // pub ft: FungibleToken,
 
@@ -84,10 +163,59 @@ pub struct Contract {
     /// The bytes for the largest possible account ID that can be registered on the contract
     pub bytes_for_longest_account_id: StorageUsage,
 
+    /// The bytes charged to the owner's storage balance for each NFT it holds
+    pub bytes_per_nft: StorageUsage,
+    /// storage_deposits[address] = $NEAR deposited by the account for storage, registration included
+    pub storage_deposits: LookupMap<AccountId, Balance>,
+    /// storage_used[address] = bytes charged to the account on top of the registration
+    pub storage_used: LookupMap<AccountId, StorageUsage>,
+
     /// mint_white_list[address] = true/false
     pub mint_white_list: LookupMap<AccountId, bool>,
     /// fee_white_list[address] = true/false
     pub fee_white_list: LookupMap<AccountId, bool>,
+
+    /// level_commitments[token_id] = pending level commitment of an unrevealed token
+    pub level_commitments: LookupMap<TokenId, LevelCommitment>,
+    /// level_revealed_at[token_id] = block height the level of the token was revealed in
+    pub level_revealed_at: LookupMap<TokenId, u64>,
+
+    /// pending_mints[address] = NFTs still owed to the account after a wrap ran out of gas
+    pub pending_mints: UnorderedMap<AccountId, u128>,
+    /// kept_ft_units[address] = NFTs the account unwrapped with nft_unwrap that are not minted again
+    pub kept_ft_units: LookupMap<AccountId, u128>,
+
+    /// skip_nft[address] = true, set by the account itself to opt out of NFT materialization
+    pub skip_nft: LookupMap<AccountId, bool>,
+
+    /// locked_tokens[token_id] = true, locked tokens are never burned by FT transfers
+    pub locked_tokens: LookupMap<TokenId, bool>,
+    /// locked_levels[address] = levels the account never wants burned by FT transfers
+    pub locked_levels: LookupMap<AccountId, Vec<u8>>,
+    /// burn_orders[address] = order in which the account's NFTs are burned by FT transfers
+    pub burn_orders: LookupMap<AccountId, BurnOrder>,
+
+    /// FIFO queue of burned token IDs waiting to be minted again, reserve_token_ids[slot] = token_id
+    pub reserve_token_ids: LookupMap<u64, TokenId>,
+    /// keeps the level and metadata of the tokens in the reserve
+    pub reserve_token_metadata: LookupMap<TokenId, TokenMetadata>,
+    /// slot of the oldest token in the reserve
+    pub reserve_head: u64,
+    /// slot the next burned token goes to
+    pub reserve_tail: u64,
+
+    /// fusion_costs[level] = number of tokens of that level fused into one token of the next level
+    pub fusion_costs: LookupMap<u8, u32>,
+
+    /// price and cooldown of a level reroll, rerolls are disabled when None
+    pub reroll_config: Option<RerollConfig>,
+    /// last_reroll_at[token_id] = block timestamp (ms) of the last reroll of the token
+    pub last_reroll_at: LookupMap<TokenId, u64>,
+    /// next index of token_metadata_by_id to rebuild the level supplies, holders and storage charges from
+    /// after a migration, None once every migrated token is counted
+    pub index_backfill_cursor: Option<u64>,
+    //switches that are currently paused
+    pub paused: Vec<PauseSwitch>,
 }
 
 /// Helper structure for keys of the persistent collections.
@@ -108,6 +236,34 @@ pub enum StorageKey {
     Accounts,
     Metadata,
     NFTMediaData,
+    LevelCommitments,
+    PendingMints,
+    SkipNft,
+    LockedTokens,
+    LockedLevels,
+    BurnOrders,
+    ReserveTokenIds,
+    ReserveTokenMetadata,
+    FusionCosts,
+    LastRerollAt,
+    StorageDeposits,
+    StorageUsed,
+    FeeVault,
+    Referrers,
+    Roles,
+    KeptFtUnits,
+    LevelSupply,
+    Holders,
+    LevelRoyalties,
+    RoyaltyRecipients,
+    LevelRoyaltyRates,
+    TokenDenominations,
+    ReserveLevelSupply,
+    NoteExtraUnits,
+    LevelRevealedAt,
+    TokensByReceiveOrder,
+    TokensByReceiveOrderInner { account_id_hash: CryptoHash },
+    TokenReceiveSeq,
 }
 
 #[near_bindgen]
@@ -123,63 +279,116 @@ impl Contract {
         Contract::internal_check_contract_meta_data(&metadata, &mediadata);
 
         //create a variable of type Self with all the fields initialized.
-        let mut contract = Self {
+        let mut contract = Contract::internal_new_state(&owner_id, &metadata, &mediadata, total_supply.0);
+
+        // Measure the bytes for the longest account ID and store it in the contract.
+        contract.measure_bytes_for_longest_account_id();
+        // Measure the bytes an NFT takes up for its owner.
+        contract.measure_bytes_per_nft();
+
+        // Register the contract account, it holds the protocol fees until they are claimed.
+        if owner_id != env::current_account_id() {
+            contract.internal_register_account(&env::current_account_id());
+        }
+
+        // Register the owner's account and set their balance to the total supply.
+        contract.internal_register_account(&owner_id);
+        contract.internal_deposit(&owner_id, total_supply.into());
+
+        // Emit an event showing that the FTs were minted
+        FtMint {
+            owner_id: &owner_id,
+            amount: &total_supply,
+            memo: Some("Initial token supply is minted"),
+        }.emit();
+
+        //return the Contract object
+        contract
+    }
+
+    // pub fn update_media(&mut self, metadata: NFTContractMetadata) {
+    //     require!(env::predecessor_account_id() == self.owner_id);
+    //     self.metadata = LazyOption::new(
+    //         StorageKey::NFTContractMetadata.try_to_vec().unwrap(),
+    //         Some(&metadata),
+    //     );
+    // }
+}
+
+impl Contract {
+    /// State of a new contract, shared with the migration which points the collections at their existing prefixes.
+    pub(crate) fn internal_new_state(owner_id: &AccountId, metadata: &NFTContractMetadata, mediadata: &NFTMediaData, total_supply: Balance) -> Self {
+        Self {
             //Storage keys are simply the prefixes used for the collections. This helps avoid data collision
             tokens_per_owner: LookupMap::new(StorageKey::TokensPerOwner.try_to_vec().unwrap()),
             level_tokens_per_owner: LookupMap::new(StorageKey::LevelTokensPerOwner.try_to_vec().unwrap()),
+            tokens_by_receive_order: LookupMap::new(StorageKey::TokensByReceiveOrder.try_to_vec().unwrap()),
+            token_receive_seq: LookupMap::new(StorageKey::TokenReceiveSeq.try_to_vec().unwrap()),
+            next_receive_seq: 0,
+            level_supply: LookupMap::new(StorageKey::LevelSupply.try_to_vec().unwrap()),
+            reserve_level_supply: LookupMap::new(StorageKey::ReserveLevelSupply.try_to_vec().unwrap()),
+            holders: UnorderedSet::new(StorageKey::Holders.try_to_vec().unwrap()),
+            default_royalty: HashMap::new(),
+            level_royalties: LookupMap::new(StorageKey::LevelRoyalties.try_to_vec().unwrap()),
+            royalty_recipients: LookupMap::new(StorageKey::RoyaltyRecipients.try_to_vec().unwrap()),
+            level_royalty_rates: LookupMap::new(StorageKey::LevelRoyaltyRates.try_to_vec().unwrap()),
+            token_denominations: LookupMap::new(StorageKey::TokenDenominations.try_to_vec().unwrap()),
+            note_extra_units: LookupMap::new(StorageKey::NoteExtraUnits.try_to_vec().unwrap()),
             tokens_by_id: LookupMap::new(StorageKey::TokensById.try_to_vec().unwrap()),
             token_metadata_by_id: UnorderedMap::new(
                 StorageKey::TokenMetadataById.try_to_vec().unwrap(),
             ),
             //set the owner_id field equal to the passed in owner_id.
             owner_id: owner_id.clone(),
+            pending_owner_id: None,
             operator: owner_id.clone(),
+            roles: UnorderedMap::new(StorageKey::Roles.try_to_vec().unwrap()),
             protocol_fee: 0,
             protocol_fee_rate: DEFAULT_PROTOCOL_FEE,
+            treasury_id: owner_id.clone(),
+            fee_splits: vec![FeeSplit { recipient: FeeRecipient::Treasury, share: FEE_SPLIT_DENOMINATOR }],
+            fee_vault: LookupMap::new(StorageKey::FeeVault.try_to_vec().unwrap()),
+            referrers: LookupMap::new(StorageKey::Referrers.try_to_vec().unwrap()),
             metadata: LazyOption::new(
                 StorageKey::NFTContractMetadata.try_to_vec().unwrap(),
-                Some(&metadata),
+                Some(metadata),
             ),
             mediadata: LazyOption::new(
                 StorageKey::NFTMediaData.try_to_vec().unwrap(),
-                Some(&mediadata),
+                Some(mediadata),
             ),
+            metadata_revealed: false,
             next_nft_id: 0,
             // Set the total supply
-            total_supply: total_supply.0,
+            total_supply,
             // Set the bytes for the longest account ID to 0 temporarily until it's calculated later
             bytes_for_longest_account_id: 0,
+            bytes_per_nft: 0,
+            storage_deposits: LookupMap::new(StorageKey::StorageDeposits.try_to_vec().unwrap()),
+            storage_used: LookupMap::new(StorageKey::StorageUsed.try_to_vec().unwrap()),
             // Storage keys are simply the prefixes used for the collections. This helps avoid data collision
             accounts: LookupMap::new(StorageKey::Accounts.try_to_vec().unwrap()),
             mint_white_list: LookupMap::new(b"mint_white".to_vec()),
             fee_white_list: LookupMap::new(b"fee_white".to_vec()),
-        };
-
-        // Measure the bytes for the longest account ID and store it in the contract.
-        contract.measure_bytes_for_longest_account_id();
-
-        // Register the owner's account and set their balance to the total supply.
-        contract.internal_register_account(&owner_id);
-        contract.internal_deposit(&owner_id, total_supply.into());
-
-        // Emit an event showing that the FTs were minted
-        FtMint {
-            owner_id: &owner_id,
-            amount: &total_supply,
-            memo: Some("Initial token supply is minted"),
-        }.emit();
-
-        //return the Contract object
-        contract
+            level_commitments: LookupMap::new(StorageKey::LevelCommitments.try_to_vec().unwrap()),
+            level_revealed_at: LookupMap::new(StorageKey::LevelRevealedAt.try_to_vec().unwrap()),
+            pending_mints: UnorderedMap::new(StorageKey::PendingMints.try_to_vec().unwrap()),
+            kept_ft_units: LookupMap::new(StorageKey::KeptFtUnits.try_to_vec().unwrap()),
+            skip_nft: LookupMap::new(StorageKey::SkipNft.try_to_vec().unwrap()),
+            locked_tokens: LookupMap::new(StorageKey::LockedTokens.try_to_vec().unwrap()),
+            locked_levels: LookupMap::new(StorageKey::LockedLevels.try_to_vec().unwrap()),
+            burn_orders: LookupMap::new(StorageKey::BurnOrders.try_to_vec().unwrap()),
+            reserve_token_ids: LookupMap::new(StorageKey::ReserveTokenIds.try_to_vec().unwrap()),
+            reserve_token_metadata: LookupMap::new(StorageKey::ReserveTokenMetadata.try_to_vec().unwrap()),
+            reserve_head: 0,
+            reserve_tail: 0,
+            fusion_costs: LookupMap::new(StorageKey::FusionCosts.try_to_vec().unwrap()),
+            reroll_config: None,
+            last_reroll_at: LookupMap::new(StorageKey::LastRerollAt.try_to_vec().unwrap()),
+            index_backfill_cursor: None,
+            paused: Vec::new(),
+        }
     }
-
-    // pub fn update_media(&mut self, metadata: NFTContractMetadata) {
-    //     require!(env::predecessor_account_id() == self.owner_id);
-    //     self.metadata = LazyOption::new(
-    //         StorageKey::NFTContractMetadata.try_to_vec().unwrap(),
-    //         Some(&metadata),
-    //     );
-    // }
 }
 
 #[cfg(test)]
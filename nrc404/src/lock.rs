@@ -0,0 +1,67 @@
+use near_sdk::require;
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Lock or unlock tokens owned by the caller. Locked tokens are never burned to cover an FT transfer.
+    #[payable]
+    pub fn nrc404_lock_tokens(&mut self, token_ids: Vec<TokenId>, locked: bool) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        for token_id in token_ids.iter() {
+            let token = self.tokens_by_id.get(token_id).expect("No token");
+            require!(token.owner_id == account_id, "Predecessor must be the token owner.");
+            if locked {
+                self.locked_tokens.insert(token_id, &true);
+            } else {
+                self.locked_tokens.remove(token_id);
+            }
+        }
+    }
+
+    /// Set the levels of the caller's NFTs that are never burned to cover an FT transfer.
+    /// Tokens whose level was revealed in the current block are not held back by it yet,
+    /// and no level is held back while the levels are hidden.
+    #[payable]
+    pub fn nrc404_set_locked_levels(&mut self, levels: Vec<u8>) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let metadata = self.metadata.get().unwrap();
+        let mut levels = levels;
+        levels.sort_unstable();
+        levels.dedup();
+        require!(levels.len() <= metadata.max_level as usize, INVALID_LEVEL);
+        for level in levels.iter() {
+            require!(*level != PENDING_LEVEL && *level <= metadata.max_level, INVALID_LEVEL);
+        }
+        if levels.is_empty() {
+            self.locked_levels.remove(&account_id);
+        } else {
+            self.locked_levels.insert(&account_id, &levels);
+        }
+    }
+
+    /// Set the order in which the caller's NFTs are burned to cover an FT transfer.
+    #[payable]
+    pub fn nrc404_set_burn_order(&mut self, burn_order: BurnOrder) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        if burn_order == BurnOrder::default() {
+            self.burn_orders.remove(&account_id);
+        } else {
+            self.burn_orders.insert(&account_id, &burn_order);
+        }
+    }
+
+    pub fn nrc404_is_token_locked(&self, token_id: TokenId) -> bool {
+        self.locked_tokens.contains_key(&token_id)
+    }
+
+    pub fn nrc404_locked_levels(&self, account_id: AccountId) -> Vec<u8> {
+        self.locked_levels.get(&account_id).unwrap_or_default()
+    }
+
+    pub fn nrc404_burn_order(&self, account_id: AccountId) -> BurnOrder {
+        self.burn_orders.get(&account_id).unwrap_or_default()
+    }
+}
@@ -15,6 +15,7 @@ pub struct NFTContractMetadata {
     pub name: String,              // required, ex. "Mosaics"
     pub symbol: String,            // required, ex. "MOSAIC"
     pub decimals: u8,              // required, ex. "18"
+    pub units_per_nft: U128,       // required, FT amount one NFT stands for, ex. "1000000000000000000000" for 1,000 tokens of 18 decimals
     pub icon: Option<String>,      // required, icon pic
     pub base_uri: Option<String>, // Centralized gateway known to have reliable access to decentralized storage assets referenced by `reference` or `media` URLs
     pub reference: Option<String>, // URL to a JSON file with more info
@@ -22,12 +23,31 @@ pub struct NFTContractMetadata {
     pub max_level: u8,             // required, ex. "1": common, "2": fine, "3": rare, "4": epic, "5": legendary
     pub enable_random_level: bool,
     pub level_probability: Option<Vec<u16>>,
+    pub level_supply_caps: Option<Vec<Option<u64>>>, // most tokens each level can have, one entry per level and the lowest level uncapped, ex. [null, null, 1000, 100, 10]
+    pub reveal_at: Option<u64>, // when the levels and level media of the tokens are shown instead of the placeholder media, Unix epoch in milliseconds
+    pub denominations: Option<Vec<u32>>, // note sizes in NFT units, ascending and starting at 1, ex. [1, 10, 100]. Single notes only if omitted
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct NFTMediaData {
     pub level_medias: Vec<String>,
+    pub level_descriptors: Option<Vec<LevelDescriptor>>, // one per level media, rendered into the metadata of the level's tokens
+    pub placeholder_media: Option<String>, // shown for every token until the collection is revealed, the levels stay hidden as well
+}
+
+/// Metadata shared by the tokens of a level. `{id}` and `{level}` in the title are replaced
+/// with the token ID and level, ex. "Epic #{id}".
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LevelDescriptor {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub media: Option<String>, // overrides the level media
+    pub media_hash: Option<Base64VecU8>,
+    pub extra: Option<String>, // ex. JSON attributes of the level
+    pub reference: Option<String>,
+    pub reference_hash: Option<Base64VecU8>,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
@@ -46,6 +66,103 @@ pub struct TokenMetadata {
     pub extra: Option<String>, // anything extra the NFT wants to store on-chain. Can be stringified JSON.
     pub reference: Option<String>, // URL to an off-chain JSON file with more info.
     pub reference_hash: Option<Base64VecU8>, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
+    #[borsh_skip]
+    pub denomination: Option<u32>, // NFT units the note stands for, kept in `token_denominations` and filled in when the token is viewed
+}
+
+/// Commitment to reveal the level of an auto-minted token from the random seed of a block at or after `reveal_height`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LevelCommitment {
+    //block height at which the token was minted
+    pub commit_height: u64,
+    //first block height whose random seed can be used to reveal the level
+    pub reveal_height: u64,
+}
+
+/// Order in which an account's NFTs are burned when an FT transfer needs more than the raw FT balance.
+/// While the levels are hidden, lowest level first burns the oldest tokens first.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum BurnOrder {
+    #[default]
+    LowestLevelFirst,
+    NewestFirst,
+    OldestFirst,
+}
+
+/// How many owned tokens a level has, next to the rate `level_probability` configures for it.
+/// Rates are out of `MAX_LEVEL_PROBABILITY` and the observed rate only counts revealed tokens.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LevelDistribution {
+    pub level: u8,
+    pub supply: U128,
+    pub observed_rate: u16,
+    pub expected_rate: Option<u16>,
+}
+
+/// Capacity of a level under its supply cap. The supply counts the tokens of the level waiting in the reserve.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LevelCapacity {
+    pub level: u8,
+    pub supply: U128,
+    pub cap: Option<U128>,
+    pub remaining: Option<U128>,
+}
+
+/// An account holding FT or NFTs. `ft_balance` is the `ft_balance_of` of the account, NFTs included.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Holder {
+    pub account_id: AccountId,
+    pub ft_balance: U128,
+    pub nft_count: U128,
+    pub level_counts: HashMap<u8, U128>,
+}
+
+/// Owner-set terms of a paid level reroll.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RerollConfig {
+    //FT paid by the token owner for each reroll
+    pub price: U128,
+    //burn the price from the total supply instead of paying it to the protocol fee account
+    pub burn: bool,
+    //minimum time between two rerolls of the same token, in milliseconds
+    pub cooldown_ms: u64,
+}
+
+/// Recipient of a share of the protocol fees.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum FeeRecipient {
+    //the treasury account of the contract
+    Treasury,
+    //the referrer of the account paying the fee, the treasury when it has none
+    Referrer,
+    //a fixed account, ex. a buyback account
+    Account(AccountId),
+}
+
+/// Share of the protocol fees, in basis points of FEE_SPLIT_DENOMINATOR.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeSplit {
+    pub recipient: FeeRecipient,
+    pub share: u16,
+}
+
+//The protocol fee settings returned from view calls.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeConfig {
+    pub protocol_fee_rate: U128,
+    pub treasury_id: AccountId,
+    pub fee_splits: Vec<FeeSplit>,
 }
 
 #[derive(BorshDeserialize, BorshSerialize)]
@@ -76,6 +193,14 @@ pub struct JsonToken {
     pub royalty: HashMap<AccountId, u32>,
 }
 
+//The number of NFTs still owed to an account, returned from view calls.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingMint {
+    pub account_id: AccountId,
+    pub count: U128,
+}
+
 pub trait NonFungibleTokenMetadata {
     //view call for returning the contract metadata
     fn nft_metadata(&self) -> NFTContractMetadata;
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use near_sdk::require;
 use crate::*;
 
@@ -12,16 +13,13 @@ impl Contract {
         //we add an optional parameter for perpetual royalties
         perpetual_royalties: Option<HashMap<AccountId, u32>>,
     ) {
-        require!(env::predecessor_account_id() == self.operator, "Illegal permissions");
-        //measure the initial storage being used on the contract
-        let initial_storage_usage = env::storage_usage();
+        self.assert_not_paused(PauseSwitch::OperatorWrap);
+        let predecessor_id = env::predecessor_account_id();
+        require!(predecessor_id == self.operator || self.internal_has_role(&predecessor_id, Role::Operator), "Illegal permissions");
+        require!(metadata.level != PENDING_LEVEL, INVALID_LEVEL);
+        //the attached deposit goes to the receiver's storage balance, which pays for the new token
+        self.internal_storage_deposit(&receiver_id, env::attached_deposit());
         self.internal_mint(env::predecessor_account_id(), metadata, receiver_id, perpetual_royalties);
-
-        //calculate the required storage which was the used - initial
-        let required_storage_in_bytes = env::storage_usage() - initial_storage_usage;
-
-        //refund any excess storage if the user attached too much. Panic if they didn't attach enough to cover the required.
-        refund_deposit(required_storage_in_bytes);
     }
 
     #[payable]
@@ -29,10 +27,109 @@ impl Contract {
         &mut self,
         count: U128,
     ) {
-        let ft_balance = self.internal_unwrap_balance_of(&env::predecessor_account_id());
+        self.assert_not_paused(PauseSwitch::Wrap);
+        let account_id = env::predecessor_account_id();
+        let ft_balance = self.internal_unwrap_balance_of(&account_id);
+        let metadata = self.metadata.get().unwrap();
+        let units_per_nft = metadata.units_per_nft.0;
+        require!(ft_balance / units_per_nft >= count.0, LESS_BALANCE);
+        let minted = self.internal_wrap_ft_to_nft_with_count(&account_id, &metadata, count.0, false);
+        // explicitly wrapped tokens are no longer kept as FT
+        let kept = self.kept_ft_units.get(&account_id).unwrap_or(0);
+        self.internal_set_kept_ft_units(&account_id, kept.saturating_sub(minted));
+    }
+
+    /// Turn the caller's chosen NFTs back into FT. Their approvals are revoked and the freed units
+    /// stay fungible, they are not minted again by the next transfer.
+    #[payable]
+    pub fn nft_unwrap(&mut self, token_ids: Vec<TokenId>) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let unique_token_ids: HashSet<&TokenId> = token_ids.iter().collect();
+        require!(!token_ids.is_empty() && unique_token_ids.len() == token_ids.len(), "Invalid token IDs");
+        let mut token_levels = vec![];
+        for token_id in token_ids.iter() {
+            let token = self.tokens_by_id.get(token_id).expect("No token");
+            require!(token.owner_id == account_id, "Predecessor must be the token owner.");
+            require!(!self.locked_tokens.contains_key(token_id), TOKEN_LOCKED);
+            token_levels.push(self.token_metadata_by_id.get(token_id).unwrap().level);
+        }
+        let metadata = self.metadata.get().unwrap();
+        let units_per_nft = metadata.units_per_nft.0;
+        let units = self.internal_unwrap_tokens(&account_id, &account_id, &token_ids, &token_levels, units_per_nft, false);
+        let kept = self.kept_ft_units.get(&account_id).unwrap_or(0);
+        self.internal_set_kept_ft_units(&account_id, kept + units);
+    }
+
+    /// Get how many NFTs the account unwrapped that are kept as FT.
+    pub fn nrc404_kept_ft_units(&self, account_id: AccountId) -> U128 {
+        U128(self.kept_ft_units.get(&account_id).unwrap_or(0))
+    }
+
+    /// Opt the caller in or out of NFT materialization.
+    /// Skipping burns all of the caller's NFTs back into FT, locked ones included, un-skipping mints NFTs for the FT balance.
+    #[payable]
+    pub fn nrc404_set_skip_nft(&mut self, skip: bool) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        if skip {
+            self.skip_nft.insert(&account_id, &true);
+            let nft_units = self.internal_nft_units_of(&account_id);
+            if nft_units > 0 {
+                // all of the caller's NFTs are burned, its locks can not hold any of them back
+                self.locked_levels.remove(&account_id);
+                for token_id in self.tokens_per_owner.get(&account_id).unwrap().iter() {
+                    self.locked_tokens.remove(&token_id);
+                }
+                let metadata = self.metadata.get().unwrap();
+                let units_per_nft = metadata.units_per_nft.0;
+                self.internal_wrap_nft_to_ft(&account_id, nft_units * units_per_nft, false);
+            }
+            self.internal_set_pending_mints(&account_id, 0);
+        } else {
+            self.skip_nft.remove(&account_id);
+            self.internal_wrap_ft_to_nft(&account_id);
+        }
+    }
+
+    /// Whether the account opted out of NFT materialization.
+    pub fn nrc404_get_skip_nft(&self, account_id: AccountId) -> bool {
+        self.skip_nft.contains_key(&account_id)
+    }
+
+    /// Mint up to `limit` of the NFTs still owed to `account_id` after an earlier wrap ran out of gas.
+    /// Anyone can call it. Returns the number of minted NFTs.
+    pub fn nrc404_process_pending(&mut self, account_id: AccountId, limit: Option<u64>) -> U128 {
+        self.assert_not_paused(PauseSwitch::Wrap);
+        let pending = self.pending_mints.get(&account_id).unwrap_or(0);
+        if pending == 0 {
+            return U128(0);
+        }
+        // the account may have been white listed or opted out of NFTs since the wrap was recorded
+        if self.mint_white_list.contains_key(&account_id) || self.skip_nft.contains_key(&account_id) || account_id == env::current_account_id() {
+            self.internal_set_pending_mints(&account_id, 0);
+            return U128(0);
+        }
+        // the account may have moved FT away since the wrap was recorded
+        let ft_balance = self.internal_unwrap_balance_of(&account_id);
         let metadata = self.metadata.get().unwrap();
-        let decimal_int = 10u128.pow(metadata.decimals as u32);
-        require!(ft_balance / decimal_int >= count.0, LESS_BALANCE);
-        self.internal_wrap_ft_to_nft_with_count(&env::predecessor_account_id(), &metadata, count.0);
+        let units_per_nft = metadata.units_per_nft.0;
+        let owed = std::cmp::min(pending, ft_balance / units_per_nft);
+        let count = std::cmp::min(owed, limit.unwrap_or(50) as u128);
+        let minted = self.internal_wrap_ft_to_nft_with_count(&account_id, &metadata, count, true);
+        self.internal_set_pending_mints(&account_id, owed - minted);
+        U128(minted)
+    }
+
+    /// Reveal the pending levels of `account_id` whose reveal block has been reached.
+    /// Anyone can call it. The levels are rolled from the random seed of the block this call lands in,
+    /// the first block at or after `reveal_height` in which the account is touched, not of a fixed block.
+    pub fn nrc404_reveal(&mut self, account_id: AccountId, limit: Option<u64>) -> u64 {
+        self.internal_reveal_levels(&account_id, limit.unwrap_or(MAX_LEVEL_REVEALS_PER_TOUCH))
+    }
+
+    /// Get the pending level commitment of an unrevealed token.
+    pub fn nrc404_level_commitment(&self, token_id: TokenId) -> Option<LevelCommitment> {
+        self.level_commitments.get(&token_id)
     }
 }
@@ -63,15 +63,17 @@ impl FungibleTokenCore for Contract {
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
         // Assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
         assert_one_yocto();
+        self.assert_not_paused(PauseSwitch::FtTransfer);
         // The sender is the user who called the method
         let sender_id = env::predecessor_account_id();
         // How many tokens the user wants to withdraw
         let mut amount: Balance = amount.into();
         // query real balance
         let ft_balance = self.internal_unwrap_balance_of(&sender_id);
-        if ft_balance < amount {
+        let unwrapped = ft_balance < amount;
+        if unwrapped {
             // wrap NFT to ft
-            self.internal_wrap_nft_to_ft(&sender_id, amount-ft_balance);
+            self.internal_wrap_nft_to_ft(&sender_id, amount-ft_balance, true);
         }
         // protocol fee
         amount = self.internal_handle_protocol_fee(&sender_id, &receiver_id, amount);
@@ -80,6 +82,10 @@ impl FungibleTokenCore for Contract {
         self.internal_transfer_ft(&sender_id, &receiver_id, amount, memo);
         // wrap ft to NFT
         self.internal_wrap_ft_to_nft(&receiver_id);
+        if unwrapped {
+            // the change of a broken note goes back into smaller notes
+            self.internal_wrap_ft_to_nft(&sender_id);
+        }
     }
 
     #[payable]
@@ -92,6 +98,7 @@ impl FungibleTokenCore for Contract {
     ) -> PromiseOrValue<U128> {
         // Assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
         assert_one_yocto();
+        self.assert_not_paused(PauseSwitch::FtTransfer);
         // The sender is the user who called the method
         let sender_id = env::predecessor_account_id();
         // How many tokens the sender wants to transfer
@@ -100,7 +107,7 @@ impl FungibleTokenCore for Contract {
         let ft_balance = self.internal_unwrap_balance_of(&sender_id);
         if ft_balance < amount {
             // wrap NFT
-            self.internal_wrap_nft_to_ft(&sender_id, amount-ft_balance);
+            self.internal_wrap_nft_to_ft(&sender_id, amount-ft_balance, true);
         }
         // protocol fee
         amount = self.internal_handle_protocol_fee(&sender_id, &receiver_id, amount);
@@ -131,11 +138,11 @@ impl FungibleTokenCore for Contract {
     fn ft_balance_of(&self, account_id: AccountId) -> U128 {
         // Return the balance of the account casted to a U128
         let ft_balance = self.accounts.get(&account_id).unwrap_or(0);
-        let nft_count = self.nft_supply_for_owner(account_id);
+        let nft_units = self.internal_nft_units_of(&account_id);
         let metadata = self.metadata.get().unwrap();
-        let decimal_int = 10u128.pow(metadata.decimals as u32);
+        let units_per_nft = metadata.units_per_nft.0;
 
-        U128::from(ft_balance.checked_add(nft_count.0 * decimal_int).expect(BALANCE_OVERFLOW))
+        U128::from(ft_balance.checked_add(nft_units * units_per_nft).expect(BALANCE_OVERFLOW))
     }
 
 }
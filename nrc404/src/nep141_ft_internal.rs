@@ -21,6 +21,7 @@ impl Contract {
         // Add the amount to the balance and insert the new balance into the accounts map
         if let Some(new_balance) = balance.checked_add(amount) {
             self.accounts.insert(account_id, &new_balance);
+            self.internal_update_holder(account_id);
         } else {
             env::panic_str("Balance overflow");
         }
@@ -34,6 +35,7 @@ impl Contract {
         // Decrease the amount from the balance and insert the new balance into the accounts map
         if let Some(new_balance) = balance.checked_sub(amount) {
             self.accounts.insert(account_id, &new_balance);
+            self.internal_update_holder(account_id);
         } else {
             env::panic_str("The account doesn't have enough balance");
         }
@@ -1,5 +1,5 @@
 use near_sdk::json_types::U128;
-use near_sdk::{env, log, AccountId, Balance, Promise};
+use near_sdk::{env, log, require, AccountId, Balance, Promise, StorageUsage};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::serde::{Deserialize, Serialize};
 
@@ -61,6 +61,39 @@ pub trait StorageManagement {
         registration_only: Option<bool>,
     ) -> StorageBalance;
 
+    // Withdraw specified amount of available Ⓝ for predecessor account.
+    //
+    // This method is safe to call. It MUST NOT remove data.
+    //
+    // `amount` is sent as a string representing an unsigned 128-bit integer. If
+    // omitted, contract MUST refund full `available` balance. If `amount` exceeds
+    // predecessor account's available balance, contract MUST panic.
+    //
+    // If predecessor account not registered, contract MUST panic.
+    //
+    // MUST require exactly 1 yoctoNEAR attached balance to prevent restricted
+    // function-call access-key call (UX wallet security)
+    //
+    // Returns the StorageBalance structure showing updated balances.
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance;
+
+    // Unregisters the predecessor account and returns the storage NEAR deposit.
+    //
+    // If the predecessor account is not registered, the function MUST return
+    // `false` without panic.
+    //
+    // If `force=true` the function SHOULD ignore existing account data, such as
+    // non-zero balances on an FT contract (that is, it should burn such balances),
+    // and close the account. Otherwise, MUST panic if caller has existing account
+    // data, such as a positive registered balance (eg token holdings).
+    //
+    // MUST require exactly 1 yoctoNEAR attached balance to prevent restricted
+    // function-call access-key call (UX wallet security)
+    //
+    // Returns `true` iff the account was successfully unregistered.
+    // Returns `false` iff account was not registered before.
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool;
+
     /****************/
     /* VIEW METHODS */
     /****************/
@@ -77,7 +110,6 @@ pub trait StorageManagement {
 
 #[near_bindgen]
 impl StorageManagement for Contract {
-    #[allow(unused_variables)]
     #[payable]
     fn storage_deposit(
         &mut self,
@@ -88,14 +120,19 @@ impl StorageManagement for Contract {
         let amount: Balance = env::attached_deposit();
         // If an account was specified, use that. Otherwise, use the predecessor account.
         let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let registration_only = registration_only.unwrap_or(false);
 
-        // If the account is already registered, refund the deposit.
+        // If the account is already registered, top up its storage balance unless it only wanted to register.
         if self.accounts.contains_key(&account_id) {
-            log!("The account is already registered, refunding the deposit");
-            if amount > 0 {
-                Promise::new(env::predecessor_account_id()).transfer(amount);
+            if registration_only {
+                log!("The account is already registered, refunding the deposit");
+                if amount > 0 {
+                    Promise::new(env::predecessor_account_id()).transfer(amount);
+                }
+            } else {
+                self.internal_storage_deposit(&account_id, amount);
             }
-            // Register the account and refund any excess $NEAR
+            // Register the account and keep the deposit above the minimum as storage balance
         } else {
             // Get the minimum required storage and ensure the deposit is at least that amount
             let min_balance = self.storage_balance_bounds().min.0;
@@ -105,15 +142,96 @@ impl StorageManagement for Contract {
 
             // Register the account
             self.internal_register_account(&account_id);
-            // Perform a refund
-            let refund = amount - min_balance;
+            // Perform a refund if the account only wanted to register
+            let deposit = if registration_only { min_balance } else { amount };
+            self.storage_deposits.insert(&account_id, &deposit);
+            let refund = amount - deposit;
             if refund > 0 {
                 Promise::new(env::predecessor_account_id()).transfer(refund);
             }
         }
 
         // Return the storage balance of the account
-        StorageBalance { total: self.storage_balance_bounds().min, available: 0.into() }
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        self.assert_indexes_backfilled();
+        let account_id = env::predecessor_account_id();
+        require!(self.accounts.contains_key(&account_id), format!("The account {} is not registered", &account_id));
+
+        // Only what isn't used up by the registration, the NFTs and the approvals can be withdrawn
+        let available = self.internal_storage_available(&account_id);
+        let amount = amount.map(|amount| amount.0).unwrap_or(available);
+        require!(amount <= available, "The amount is greater than the available storage balance");
+        if amount > 0 {
+            let total = self.storage_deposits.get(&account_id).unwrap_or(0);
+            self.storage_deposits.insert(&account_id, &(total - amount));
+            Promise::new(account_id.clone()).transfer(amount);
+        }
+
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        self.assert_indexes_backfilled();
+        let account_id = env::predecessor_account_id();
+        let force = force.unwrap_or(false);
+        let ft_balance = match self.accounts.get(&account_id) {
+            Some(ft_balance) => ft_balance,
+            None => {
+                log!("The account {} is not registered", &account_id);
+                return false;
+            }
+        };
+        let nft_count = self.nft_supply_for_owner(account_id.clone()).0;
+        let nft_units = self.internal_nft_units_of(&account_id);
+        if !force {
+            require!(ft_balance == 0 && nft_count == 0, "Can't unregister the account with the positive balance without force");
+        }
+        // fees and roles aren't burned with the balance, even with force
+        require!(self.fee_vault.get(&account_id).unwrap_or(0) == 0, UNCLAIMED_FEES);
+        require!(self.roles.get(&account_id).is_none(), ACCOUNT_HAS_ROLES);
+
+        // Burn what the account still holds, NFTs first
+        if nft_count > 0 {
+            let token_ids: Vec<TokenId> = self.tokens_per_owner.get(&account_id).unwrap().to_vec();
+            for token_id in token_ids.iter() {
+                self.internal_burn_token(&account_id, token_id, Some("Account unregistered".to_string()));
+            }
+        }
+        let metadata = self.metadata.get().unwrap();
+        let units_per_nft = metadata.units_per_nft.0;
+        self.total_supply -= ft_balance + nft_units * units_per_nft;
+        // the burned NFTs are already covered by their nft_burn events
+        if ft_balance > 0 {
+            FtBurn {
+                owner_id: &account_id,
+                amount: &U128(ft_balance),
+                memo: Some("Account unregistered"),
+            }.emit();
+        }
+
+        self.accounts.remove(&account_id);
+        self.holders.remove(&account_id);
+        self.level_tokens_per_owner.remove(&account_id);
+        self.pending_mints.remove(&account_id);
+        self.kept_ft_units.remove(&account_id);
+        self.skip_nft.remove(&account_id);
+        self.locked_levels.remove(&account_id);
+        self.burn_orders.remove(&account_id);
+        self.referrers.remove(&account_id);
+        self.royalty_recipients.remove(&account_id);
+        self.storage_used.remove(&account_id);
+        let total = self.storage_deposits.remove(&account_id).unwrap_or(0);
+        if total > 0 {
+            Promise::new(account_id).transfer(total);
+        }
+        true
     }
 
     fn storage_balance_bounds(&self) -> StorageBalanceBounds {
@@ -121,19 +239,134 @@ impl StorageManagement for Contract {
         let required_storage_balance =
             Balance::from(self.bytes_for_longest_account_id) * env::storage_byte_cost();
 
-        // Storage balance bounds will have min == max == required_storage_balance
+        // Registration costs min, every NFT held costs another bytes_per_nft so there is no max
         StorageBalanceBounds {
             min: required_storage_balance.into(),
-            max: Some(required_storage_balance.into()),
+            max: None,
         }
     }
 
     fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
-        // Get the storage balance of the account. Available will always be 0 since you can't overpay for storage.
+        // Get the storage balance of the account. Available is what the registration, NFTs and approvals don't use up.
         if self.accounts.contains_key(&account_id) {
-            Some(StorageBalance { total: self.storage_balance_bounds().min, available: 0.into() })
+            Some(StorageBalance {
+                total: self.storage_deposits.get(&account_id).unwrap_or(0).into(),
+                available: self.internal_storage_available(&account_id).into(),
+            })
         } else {
             None
         }
     }
 }
+
+#[near_bindgen]
+impl Contract {
+    /// Get the $NEAR an NFT takes up from the storage balance of its owner.
+    pub fn nrc404_storage_cost_per_nft(&self) -> U128 {
+        U128(Balance::from(self.bytes_per_nft) * env::storage_byte_cost())
+    }
+}
+
+impl Contract {
+    /// Internal method for measuring how many bytes an NFT takes up for its owner.
+    /// This will mint a token for the longest possible account ID into a new level set, measure the storage and remove it.
+    /// It is called in the initialization function.
+    pub(crate) fn measure_bytes_per_nft(&mut self) {
+        let initial_storage_usage = env::storage_usage();
+        let tmp_account_id = AccountId::new_unchecked("a".repeat(64));
+        let tmp_token_id = u128::MAX.to_string();
+        let tmp_token = Token {
+            owner_id: tmp_account_id.clone(),
+            approved_account_ids: Default::default(),
+            next_approval_id: 0,
+            royalty: Default::default(),
+        };
+        let tmp_metadata = TokenMetadata {
+            level: PENDING_LEVEL, title: None, description: None,
+            media: None,
+            media_hash: None, copies: None, issued_at: Some(env::block_timestamp_ms()), expires_at: None,
+            starts_at: None, updated_at: Some(env::block_timestamp_ms()), extra: None, reference: None, reference_hash: None,
+            denomination: None,
+        };
+        self.tokens_by_id.insert(&tmp_token_id, &tmp_token);
+        self.token_metadata_by_id.insert(&tmp_token_id, &tmp_metadata);
+        self.level_commitments.insert(&tmp_token_id, &LevelCommitment { commit_height: 0, reveal_height: 0 });
+        self.token_denominations.insert(&tmp_token_id, &u32::MAX);
+        self.internal_add_token_to_owner(&tmp_account_id, &tmp_token_id, &PENDING_LEVEL);
+        self.bytes_per_nft = env::storage_usage() - initial_storage_usage;
+        self.internal_remove_token_from_owner(&tmp_account_id, &tmp_token_id, &PENDING_LEVEL);
+        self.token_denominations.remove(&tmp_token_id);
+        self.level_tokens_per_owner.remove(&tmp_account_id);
+        self.level_commitments.remove(&tmp_token_id);
+        self.token_metadata_by_id.remove(&tmp_token_id);
+        self.tokens_by_id.remove(&tmp_token_id);
+    }
+
+    /// Internal method for the part of the account's storage balance that isn't used up.
+    pub(crate) fn internal_storage_available(&self, account_id: &AccountId) -> Balance {
+        let total = self.storage_deposits.get(account_id).unwrap_or(0);
+        let used = Balance::from(self.bytes_for_longest_account_id + self.storage_used.get(account_id).unwrap_or(0))
+            * env::storage_byte_cost();
+        total.saturating_sub(used)
+    }
+
+    /// Internal method for adding some $NEAR to the storage balance of a registered account.
+    pub(crate) fn internal_storage_deposit(&mut self, account_id: &AccountId, amount: Balance) {
+        require!(self.accounts.contains_key(account_id), format!("The account {} is not registered", account_id));
+        let total = self.storage_deposits.get(account_id).unwrap_or(0);
+        self.storage_deposits.insert(account_id, &(total + amount));
+    }
+
+    /// Internal method for checking whether the account's storage balance can pay for `bytes` more.
+    pub(crate) fn internal_storage_has_room(&self, account_id: &AccountId, bytes: StorageUsage) -> bool {
+        self.internal_storage_available(account_id) >= Balance::from(bytes) * env::storage_byte_cost()
+    }
+
+    /// Internal method for charging `bytes` to the account's storage balance. Panics if the balance doesn't cover it.
+    pub(crate) fn internal_storage_charge(&mut self, account_id: &AccountId, bytes: StorageUsage) {
+        // the contract pays for what it holds itself
+        if bytes == 0 || account_id == &env::current_account_id() {
+            return;
+        }
+        require!(self.internal_storage_has_room(account_id, bytes), STORAGE_INSUFFICIENT);
+        self.internal_storage_force_charge(account_id, bytes);
+    }
+
+    /// Internal method for charging `bytes` the first release already paid for to a migrated account.
+    /// The first release kept no storage balances, so the account is credited with the charge
+    /// and, the first time, with the registration it paid for.
+    pub(crate) fn internal_storage_charge_migrated(&mut self, account_id: &AccountId, bytes: StorageUsage) {
+        if account_id == &env::current_account_id() {
+            return;
+        }
+        let total = match self.storage_deposits.get(account_id) {
+            Some(total) => total,
+            None => self.storage_balance_bounds().min.0,
+        };
+        self.storage_deposits.insert(account_id, &(total + Balance::from(bytes) * env::storage_byte_cost()));
+        self.internal_storage_force_charge(account_id, bytes);
+    }
+
+    /// Internal method for charging `bytes` to the account's storage balance even if it goes above the deposit.
+    /// Only used to give back storage the account released earlier in the same flow.
+    pub(crate) fn internal_storage_force_charge(&mut self, account_id: &AccountId, bytes: StorageUsage) {
+        if bytes == 0 || account_id == &env::current_account_id() {
+            return;
+        }
+        let used = self.storage_used.get(account_id).unwrap_or(0);
+        self.storage_used.insert(account_id, &(used + bytes));
+    }
+
+    /// Internal method for crediting `bytes` back to the account's storage balance.
+    pub(crate) fn internal_storage_release(&mut self, account_id: &AccountId, bytes: StorageUsage) {
+        if bytes == 0 {
+            return;
+        }
+        let used = self.storage_used.get(account_id).unwrap_or(0).saturating_sub(bytes);
+        if used == 0 {
+            self.storage_used.remove(account_id);
+        } else {
+            self.storage_used.insert(account_id, &used);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::*;
-use near_sdk::{ext_contract, Gas, PromiseResult};
+use near_sdk::{ext_contract, require, Gas, PromiseResult};
 
 const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(10_000_000_000_000);
 const GAS_FOR_NFT_ON_TRANSFER: Gas = Gas(25_000_000_000_000);
@@ -50,7 +50,7 @@ trait NonFungibleTokenReceiver {
     this is stored on THIS contract and is meant to analyze what happened in the cross contract call when nft_on_transfer was called
     as part of the nft_transfer_call method
 */
-trait NonFungibleTokenResolver {
+pub(crate) trait NonFungibleTokenResolver {
     fn nft_resolve_transfer(
         &mut self,
         //we introduce an authorized ID for logging the transfer event
@@ -82,6 +82,8 @@ impl NonFungibleTokenCore for Contract {
         assert_one_yocto();
         //get the sender to transfer the token from the sender to the receiver
         let sender_id = env::predecessor_account_id();
+        //only nft_transfer_call redeems a token sent to the contract, anywhere else it would be stranded
+        require!(receiver_id != env::current_account_id(), NFT_TO_CONTRACT);
 
         //call the internal transfer method and get back the previous token so we can refund the approved account IDs
         let previous_token = self.internal_transfer(
@@ -93,8 +95,8 @@ impl NonFungibleTokenCore for Contract {
         );
 
         //we refund the owner for releasing the storage used up by the approved account IDs
-        refund_approved_account_ids(
-            previous_token.owner_id.clone(),
+        self.refund_approved_account_ids(
+            &previous_token.owner_id,
             &previous_token.approved_account_ids,
         );
     }
@@ -165,14 +167,16 @@ impl NonFungibleTokenCore for Contract {
             //we'll get the metadata for that token
             let mut metadata = self.token_metadata_by_id.get(&token_id).unwrap();
             let mediadata = self.mediadata.get().unwrap();
-            metadata.media = self.internal_get_nft_media(&self.metadata.get().unwrap(), &mediadata, &metadata);
+            let royalty = self.internal_effective_royalty(&token.royalty, metadata.level);
+            self.internal_render_token_metadata(&self.metadata.get().unwrap(), &mediadata, &token_id, &mut metadata);
+            metadata.denomination = Some(self.internal_token_denomination(&token_id));
             //we return the JsonToken (wrapped by Some since we return an option)
             Some(JsonToken {
                 token_id,
                 owner_id: token.owner_id,
                 metadata,
                 approved_account_ids: token.approved_account_ids,
-                royalty: token.royalty,
+                royalty,
             })
         } else { //if there wasn't a token ID in the tokens_by_id collection, we return None
             None
@@ -180,6 +184,33 @@ impl NonFungibleTokenCore for Contract {
     }
 }
 
+#[near_bindgen]
+impl Contract {
+    /// Receiver side of nft_transfer_call when the receiver is this contract. The token is redeemed:
+    /// it goes back to the reserve and the FT of its units is credited to the previous owner.
+    /// Returns false so nft_resolve_transfer keeps the transfer.
+    #[allow(unused_variables)]
+    pub fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        //only tokens of this contract can be redeemed
+        require!(env::predecessor_account_id() == env::current_account_id(), "Only tokens of this contract can be redeemed");
+        let current_account_id = env::current_account_id();
+        let token_meta = self.token_metadata_by_id.get(&token_id).expect("No token");
+        let metadata = self.metadata.get().unwrap();
+        let units_per_nft = metadata.units_per_nft.0;
+        let units = self.internal_unwrap_tokens(&current_account_id, &previous_owner_id, &[token_id], &[token_meta.level], units_per_nft, false);
+        //the redeemed units stay fungible, like an explicit nft_unwrap
+        let kept = self.kept_ft_units.get(&previous_owner_id).unwrap_or(0);
+        self.internal_set_kept_ft_units(&previous_owner_id, kept + units);
+        PromiseOrValue::Value(false)
+    }
+}
+
 #[near_bindgen]
 impl NonFungibleTokenResolver for Contract {
     //resolves the cross contract call when calling nft_on_transfer in the nft_transfer_call method
@@ -209,7 +240,7 @@ impl NonFungibleTokenResolver for Contract {
                         revert the original transfer and thus we can just return true since nothing went wrong.
                     */
                     //we refund the owner for releasing the storage used up by the approved account IDs
-                    refund_approved_account_ids(owner_id, &approved_account_ids);
+                    self.refund_approved_account_ids(&owner_id, &approved_account_ids);
                     return true;
                 }
             }
@@ -219,7 +250,7 @@ impl NonFungibleTokenResolver for Contract {
         let mut token = if let Some(token) = self.tokens_by_id.get(&token_id) {
             if token.owner_id != receiver_id {
                 //we refund the owner for releasing the storage used up by the approved account IDs
-                refund_approved_account_ids(owner_id, &approved_account_ids);
+                self.refund_approved_account_ids(&owner_id, &approved_account_ids);
                 // The token is not owner by the receiver anymore. Can't return it.
                 return true;
             }
@@ -227,7 +258,7 @@ impl NonFungibleTokenResolver for Contract {
         //if there isn't a token object, it was burned and so we return true
         } else {
             //we refund the owner for releasing the storage used up by the approved account IDs
-            refund_approved_account_ids(owner_id, &approved_account_ids);
+            self.refund_approved_account_ids(&owner_id, &approved_account_ids);
             return true;
         };
         let token_meta = self.token_metadata_by_id.get(&token_id).unwrap();
@@ -236,12 +267,15 @@ impl NonFungibleTokenResolver for Contract {
         self.internal_remove_token_from_owner(&receiver_id.clone(), &token_id, &token_meta.level);
         //we add the token to the original owner
         self.internal_add_token_to_owner(&owner_id, &token_id, &token_meta.level);
+        //the storage of the token goes back to the original owner, who just released it
+        self.internal_storage_release(&receiver_id, self.bytes_per_nft);
+        self.internal_storage_force_charge(&owner_id, self.bytes_per_nft);
 
         //we change the token struct's owner to be the original owner
         token.owner_id = owner_id.clone();
 
         //we refund the receiver any approved account IDs that they may have set on the token
-        refund_approved_account_ids(receiver_id.clone(), &token.approved_account_ids);
+        self.refund_approved_account_ids(&receiver_id, &token.approved_account_ids);
         //reset the approved account IDs to what they were before the transfer
         token.approved_account_ids = approved_account_ids;
 
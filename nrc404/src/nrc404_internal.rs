@@ -5,6 +5,18 @@ use sha2::{Sha256, Digest};
 impl Contract {
 
     pub(crate) fn internal_check_contract_meta_data(metadata: &NFTContractMetadata, mediadata: &NFTMediaData) {
+        require!(metadata.units_per_nft.0 > 0, INVALID_UNITS_PER_NFT);
+        if let Some(level_descriptors) = &mediadata.level_descriptors {
+            require!(level_descriptors.len() == mediadata.level_medias.len(), INVALID_LEVEL_DESCRIPTORS);
+        }
+        if let Some(denominations) = &metadata.denominations {
+            require!(denominations.first() == Some(&1), INVALID_DENOMINATIONS);
+            require!(denominations.windows(2).all(|pair| pair[0] < pair[1]), INVALID_DENOMINATIONS);
+        }
+        if let Some(level_supply_caps) = &metadata.level_supply_caps {
+            // rolls fall back towards the lowest level, so it must always have room
+            require!(level_supply_caps.len() == metadata.max_level as usize && level_supply_caps.first() == Some(&None), INVALID_LEVEL_SUPPLY_CAPS);
+        }
         if !metadata.enable_random_level {
             require!(1 == (mediadata.level_medias.clone().len() as u8), INVALID_LEVEL_INITIAL);
             return;
@@ -19,107 +31,399 @@ impl Contract {
         require!(total_probability == MAX_LEVEL_PROBABILITY, INVALID_LEVEL_INITIAL);
     }
 
+    /// Note sizes in NFT units from the smallest to the largest.
+    pub(crate) fn internal_denominations(metadata: &NFTContractMetadata) -> Vec<u32> {
+        metadata.denominations.clone().unwrap_or_else(|| vec![1])
+    }
+
     pub(crate) fn internal_get_and_use_next_nft_id(&mut self) -> u128 {
         let next_nft_id = self.next_nft_id;
         self.next_nft_id += 1;
         return next_nft_id;
     }
 
-    pub(crate) fn internal_wrap_nft_to_ft(&mut self, account_id: &AccountId, less_amount: Balance) {
+    pub(crate) fn internal_wrap_nft_to_ft(&mut self, account_id: &AccountId, less_amount: Balance, auto: bool) {
         require!(self.tokens_per_owner.contains_key(account_id), LESS_BALANCE);
-        let token_set = self.tokens_per_owner.get(account_id).unwrap();
         let metadata = self.metadata.get().unwrap();
-        let decimal_int = 10u128.pow(metadata.decimals as u32);
-        require!(token_set.len() as u128 * decimal_int >= less_amount, LESS_BALANCE);
-        // wrap from low level nft
-        let level_token_set = self.level_tokens_per_owner.get(account_id).unwrap();
-        let mut need_amount = less_amount;
-        let mut need_del_token_id = vec![];
-        let mut need_del_token_level = vec![];
-        for i in 1..(metadata.max_level+1) {
-            let level_token_ids_op = level_token_set.get(&i);
-            if level_token_ids_op.is_none() {
-                continue;
-            }
-            let level_token_ids = level_token_ids_op.unwrap();
-            for token_id in level_token_ids.iter() {
-                need_del_token_id.push(token_id.clone());
-                need_del_token_level.push(i);
-                if need_amount > decimal_int {
-                    need_amount -= decimal_int;
-                } else if need_amount <= decimal_int {
-                    need_amount = 0;
-                    break
-                }
-            }
-            if need_amount == 0 {
-                break;
-            }
-        }
+        let units_per_nft = metadata.units_per_nft.0;
+        require!(self.internal_nft_units_of(account_id) * units_per_nft >= less_amount, LESS_BALANCE);
+        // fix the levels that can be revealed before choosing which tokens to burn
+        self.internal_reveal_levels(account_id, MAX_LEVEL_REVEALS_PER_TOUCH);
+        let need_units = less_amount.div_ceil(units_per_nft);
+        let (need_del_token_id, need_del_token_level) = self.internal_get_burnable_tokens(account_id, &metadata, need_units);
+        let found_units: u128 = need_del_token_id.iter().map(|token_id| self.internal_token_denomination(token_id) as u128).sum();
+        require!(found_units >= need_units, NFT_LOCKED);
         if need_del_token_id.len() == 0 {
             return;
         }
+        self.internal_unwrap_tokens(account_id, account_id, &need_del_token_id, &need_del_token_level, units_per_nft, auto);
+    }
+
+    /// Move the given tokens of `holder_id` into the reserve and credit `units_per_nft` of FT for each of their units to `account_id`.
+    /// Returns the number of unwrapped units.
+    pub(crate) fn internal_unwrap_tokens(&mut self, holder_id: &AccountId, account_id: &AccountId, token_ids: &[TokenId], token_levels: &[u8], units_per_nft: u128, auto: bool) -> u128 {
+        let mut total_units = 0;
         let mut total_user_added = 0;
-        for (index, del_token_id) in need_del_token_id.iter().enumerate() {
-            self.internal_remove_token_from_owner(account_id, &del_token_id, &need_del_token_level.get(index).unwrap());
-            self.tokens_by_id.remove(&del_token_id);
-            self.token_metadata_by_id.remove(&del_token_id);
+        for (del_token_id, del_token_level) in token_ids.iter().zip(token_levels.iter()) {
+            let denomination = self.internal_token_denomination(del_token_id) as u128;
+            self.internal_remove_token_from_owner(holder_id, del_token_id, del_token_level);
+            self.internal_storage_release(holder_id, self.bytes_per_nft);
+            self.internal_deposit_reserve(holder_id, del_token_id);
             // add balance
-            total_user_added += decimal_int;
-
-            // Construct the mint log as per the events standard.
-            let nft_burn_log: EventLog = EventLog {
-                // Standard name ("nep171").
-                standard: NFT_STANDARD_NAME.to_string(),
-                // Version of the standard ("nft-1.0.0").
-                version: NFT_METADATA_SPEC.to_string(),
-                // The data related with the event stored in a vector.
-                event: EventLogVariant::NftBurn(vec![NftMintLog {
-                    // Owner of the token.
-                    owner_id: account_id.to_string(),
-                    // Vector of token IDs that were minted.
-                    token_ids: vec![del_token_id.to_string()],
-                    // An optional memo to include.
-                    memo: None,
-                }]),
-            };
-
-            // Log the serialized json.
-            env::log_str(&nft_burn_log.to_string());
+            total_units += denomination;
+            total_user_added += denomination * units_per_nft;
         }
         self.internal_deposit(account_id, total_user_added);
+        FtMint {
+            owner_id: account_id,
+            amount: &U128(total_user_added),
+            memo: Some(NFT_TO_FT_MEMO),
+        }.emit();
+
+        NftUnwrap {
+            owner_id: account_id,
+            token_ids,
+            amount: &U128(total_user_added),
+            auto,
+        }.emit();
+        total_units
+    }
+
+    /// Move a token that was already removed from its owner into the reserve, keeping its level and metadata.
+    /// Only single notes are recycled, a larger note is broken for good.
+    pub(crate) fn internal_deposit_reserve(&mut self, account_id: &AccountId, token_id: &TokenId) {
+        let token = self.tokens_by_id.remove(token_id).expect("No token");
+        //refund the owner for releasing the storage used up by the approved account IDs
+        self.refund_approved_account_ids(account_id, &token.approved_account_ids);
+        let mut token_metadata = self.token_metadata_by_id.remove(token_id).expect("No token");
+        let memo = if self.token_denominations.remove(token_id).is_some() {
+            NOTE_BREAK_MEMO
+        } else {
+            token_metadata.updated_at = Some(env::block_timestamp_ms());
+            self.reserve_token_metadata.insert(token_id, &token_metadata);
+            self.reserve_token_ids.insert(&self.reserve_tail, token_id);
+            self.reserve_tail += 1;
+            let reserve_level_supply = self.reserve_level_supply.get(&token_metadata.level).unwrap_or(0);
+            self.reserve_level_supply.insert(&token_metadata.level, &(reserve_level_supply + 1));
+            RESERVE_DEPOSIT_MEMO
+        };
+        // an unrevealed token gets a fresh commitment when it leaves the reserve
+        self.level_commitments.remove(token_id);
+        self.level_revealed_at.remove(token_id);
+        self.locked_tokens.remove(token_id);
+        self.last_reroll_at.remove(token_id);
+
+        // Construct the burn log as per the events standard.
+        let nft_burn_log: EventLog = EventLog {
+            // Standard name ("nep171").
+            standard: NFT_STANDARD_NAME.to_string(),
+            // Version of the standard ("nft-1.0.0").
+            version: NFT_METADATA_SPEC.to_string(),
+            // The data related with the event stored in a vector.
+            event: EventLogVariant::NftBurn(vec![NftMintLog {
+                // Owner of the token.
+                owner_id: account_id.to_string(),
+                // Vector of token IDs that were burned.
+                token_ids: vec![token_id.to_string()],
+                // An optional memo to include.
+                memo: Some(memo.to_string()),
+            }]),
+        };
+
+        // Log the serialized json.
+        env::log_str(&nft_burn_log.to_string());
     }
 
-    pub(crate) fn internal_wrap_ft_to_nft_with_count(&mut self, account_id: &AccountId, metadata: &NFTContractMetadata, count: u128) {
-        for _ in 0..count {
+    /// Destroy a token owned by `account_id` for good, without crediting any FT for it.
+    pub(crate) fn internal_burn_token(&mut self, account_id: &AccountId, token_id: &TokenId, memo: Option<String>) -> TokenMetadata {
+        let token = self.tokens_by_id.remove(token_id).expect("No token");
+        let token_metadata = self.token_metadata_by_id.remove(token_id).expect("No token");
+        self.internal_remove_token_from_owner(account_id, token_id, &token_metadata.level);
+        self.internal_storage_release(account_id, self.bytes_per_nft);
+        self.token_denominations.remove(token_id);
+        self.level_commitments.remove(token_id);
+        self.level_revealed_at.remove(token_id);
+        self.locked_tokens.remove(token_id);
+        self.last_reroll_at.remove(token_id);
+        //refund the owner for releasing the storage used up by the approved account IDs
+        self.refund_approved_account_ids(account_id, &token.approved_account_ids);
+
+        // Construct the burn log as per the events standard.
+        let nft_burn_log: EventLog = EventLog {
+            // Standard name ("nep171").
+            standard: NFT_STANDARD_NAME.to_string(),
+            // Version of the standard ("nft-1.0.0").
+            version: NFT_METADATA_SPEC.to_string(),
+            // The data related with the event stored in a vector.
+            event: EventLogVariant::NftBurn(vec![NftMintLog {
+                // Owner of the token.
+                owner_id: account_id.to_string(),
+                // Vector of token IDs that were burned.
+                token_ids: vec![token_id.to_string()],
+                // An optional memo to include.
+                memo,
+            }]),
+        };
+
+        // Log the serialized json.
+        env::log_str(&nft_burn_log.to_string());
+
+        token_metadata
+    }
+
+    /// Take the oldest token out of the reserve, if any.
+    pub(crate) fn internal_withdraw_reserve(&mut self) -> Option<(TokenId, TokenMetadata)> {
+        if self.reserve_head == self.reserve_tail {
+            return None;
+        }
+        let token_id = self.reserve_token_ids.remove(&self.reserve_head).unwrap();
+        self.reserve_head += 1;
+        let token_metadata = self.reserve_token_metadata.remove(&token_id).unwrap();
+        let reserve_level_supply = self.reserve_level_supply.get(&token_metadata.level).unwrap_or(0);
+        if reserve_level_supply <= 1 {
+            self.reserve_level_supply.remove(&token_metadata.level);
+        } else {
+            self.reserve_level_supply.insert(&token_metadata.level, &(reserve_level_supply - 1));
+        }
+        Some((token_id, token_metadata))
+    }
+
+    /// Pick unlocked tokens of the account worth at least `count` units following its burn order.
+    /// Tokens still waiting for their level are taken first when burning from the lowest level.
+    /// Notes are spent in that order as long as they fit, then the smallest skipped note covering the rest is broken.
+    /// The tokens are walked lazily and the walk stops as soon as enough units are picked.
+    pub(crate) fn internal_get_burnable_tokens(&self, account_id: &AccountId, metadata: &NFTContractMetadata, count: u128) -> (Vec<TokenId>, Vec<u8>) {
+        let mut token_ids = vec![];
+        let mut token_levels = vec![];
+        // hidden levels can't decide what gets burned, or whether the transfer fails
+        let levels_hidden = self.internal_levels_hidden();
+        let locked_levels = if levels_hidden { vec![] } else { self.locked_levels.get(account_id).unwrap_or_default() };
+        let burn_order = match self.burn_orders.get(account_id).unwrap_or_default() {
+            BurnOrder::LowestLevelFirst if levels_hidden => BurnOrder::OldestFirst,
+            burn_order => burn_order,
+        };
+        let mut remaining = count;
+        let mut smallest_unused: Option<(TokenId, u8, u128)> = None;
+        // offer the next token in the burn order, returns true once enough units are picked
+        let mut offer = |token_id: TokenId, level: u8| -> bool {
+            // a level revealed in this block doesn't lock the token, or a revert could undo an unwanted roll
+            if self.locked_tokens.contains_key(&token_id) || (locked_levels.contains(&level) && self.internal_level_settled(&token_id)) {
+                return false;
+            }
+            // notes are taken in the burn order as long as they fit in what is left
+            let denomination = self.internal_token_denomination(&token_id) as u128;
+            if denomination <= remaining {
+                remaining -= denomination;
+                token_ids.push(token_id);
+                token_levels.push(level);
+            } else if smallest_unused.as_ref().is_none_or(|(_, _, smallest)| denomination < *smallest) {
+                smallest_unused = Some((token_id, level, denomination));
+            }
+            remaining == 0
+        };
+        match burn_order {
+            BurnOrder::LowestLevelFirst => {
+                if let Some(level_token_set) = self.level_tokens_per_owner.get(account_id) {
+                    'levels: for level in PENDING_LEVEL..(metadata.max_level+1) {
+                        if let Some(level_token_ids) = level_token_set.get(&level) {
+                            for token_id in level_token_ids.iter() {
+                                if offer(token_id, level) {
+                                    break 'levels;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            BurnOrder::NewestFirst | BurnOrder::OldestFirst => {
+                if let Some(receive_order) = self.tokens_by_receive_order.get(account_id) {
+                    let token_ids: Box<dyn Iterator<Item = (u64, TokenId)>> = if burn_order == BurnOrder::NewestFirst {
+                        Box::new(receive_order.iter_rev())
+                    } else {
+                        Box::new(receive_order.iter())
+                    };
+                    for (_, token_id) in token_ids {
+                        let level = self.token_metadata_by_id.get(&token_id).expect("No token").level;
+                        if offer(token_id, level) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        if remaining > 0 {
+            // every skipped note is larger than what is left, break the first of the smallest ones
+            if let Some((token_id, level, _)) = smallest_unused {
+                token_ids.push(token_id);
+                token_levels.push(level);
+            }
+        }
+        (token_ids, token_levels)
+    }
+
+    /// Mint NFTs worth up to `count` units out of the account's FT balance, in the largest notes that fit.
+    /// Returns how many units were minted before running low on gas.
+    pub(crate) fn internal_wrap_ft_to_nft_with_count(&mut self, account_id: &AccountId, metadata: &NFTContractMetadata, count: u128, auto: bool) -> u128 {
+        let denominations = Contract::internal_denominations(metadata);
+        let mut token_ids = vec![];
+        let mut minted = 0;
+        while minted < count {
+            let denomination = *denominations.iter().rev().find(|denomination| **denomination as u128 <= count - minted).unwrap();
             if self.internal_get_remaining_gas() < MAX_RESERVED_WRAP_GAS.0 {
                 break;
             }
-            let level = self.internal_get_new_level(account_id, &metadata);
-            let metadata = TokenMetadata {
-                level, title: None, description: None,
-                media: None,
-                media_hash: None, copies: None, issued_at: Some(env::block_timestamp_ms()), expires_at: None,
-                starts_at: None, updated_at: Some(env::block_timestamp_ms()), extra: None, reference: None, reference_hash: None,
+            // pause when the storage balance can't pay for another token, the rest stays as FT
+            if !self.internal_storage_has_room(account_id, self.bytes_per_nft) {
+                break;
+            }
+            // single tokens in the reserve are minted again before new IDs are created
+            let reserved = if denomination == 1 { self.internal_withdraw_reserve() } else { None };
+            let (token_id, level) = if let Some((token_id, mut token_metadata)) = reserved {
+                let level = token_metadata.level;
+                token_metadata.updated_at = Some(env::block_timestamp_ms());
+                self.internal_mint_token(account_id.clone(), token_id.clone(), token_metadata, account_id.clone(), None, Some(RESERVE_WITHDRAW_MEMO.to_string()));
+                (token_id, level)
+            } else {
+                // random levels are committed now and revealed from the seed of a later block
+                let level = if metadata.enable_random_level { PENDING_LEVEL } else { DEFAULT_LEVEL };
+                let token_metadata = TokenMetadata {
+                    level, title: None, description: None,
+                    media: None,
+                    media_hash: None, copies: None, issued_at: Some(env::block_timestamp_ms()), expires_at: None,
+                    starts_at: None, updated_at: Some(env::block_timestamp_ms()), extra: None, reference: None, reference_hash: None,
+                    denomination: Some(denomination),
+                };
+                (self.internal_mint(account_id.clone(), token_metadata, account_id.clone(), None), level)
             };
-            self.internal_mint(account_id.clone(), metadata, account_id.clone(), None);
+            if level == PENDING_LEVEL {
+                self.internal_commit_level(account_id, &token_id);
+            }
+            token_ids.push(token_id);
+            minted += denomination as u128;
+        }
+        if minted > 0 {
+            NftWrap {
+                owner_id: account_id,
+                token_ids: &token_ids,
+                amount: &U128(minted * metadata.units_per_nft.0),
+                auto,
+            }.emit();
+        }
+        minted
+    }
+
+    pub(crate) fn internal_set_kept_ft_units(&mut self, account_id: &AccountId, count: u128) {
+        if count == 0 {
+            self.kept_ft_units.remove(account_id);
+        } else {
+            self.kept_ft_units.insert(account_id, &count);
+        }
+    }
+
+    pub(crate) fn internal_set_pending_mints(&mut self, account_id: &AccountId, count: u128) {
+        if count == 0 {
+            self.pending_mints.remove(account_id);
+        } else {
+            self.pending_mints.insert(account_id, &count);
+        }
+    }
+
+    /// Whether the level of the token was revealed before this block. Nothing that can fail may depend on
+    /// a level revealed in the current block, the revert would let the holder roll again in the next one.
+    pub(crate) fn internal_level_settled(&self, token_id: &TokenId) -> bool {
+        self.level_revealed_at.get(token_id).is_none_or(|revealed_at| revealed_at != env::block_height())
+    }
+
+    pub(crate) fn internal_commit_level(&mut self, account_id: &AccountId, token_id: &TokenId) {
+        let commitment = LevelCommitment {
+            commit_height: env::block_height(),
+            reveal_height: env::block_height() + LEVEL_REVEAL_DELAY_BLOCKS,
+        };
+        self.level_commitments.insert(token_id, &commitment);
+
+        LevelCommit {
+            owner_id: account_id,
+            token_id,
+            commit_height: commitment.commit_height,
+            reveal_height: commitment.reveal_height,
+        }.emit();
+    }
+
+    /// Reveal up to `limit` pending levels of the account whose reveal height has been reached.
+    /// Returns the number of revealed tokens.
+    pub(crate) fn internal_reveal_levels(&mut self, account_id: &AccountId, limit: u64) -> u64 {
+        let metadata = self.metadata.get().unwrap();
+        if !metadata.enable_random_level {
+            return 0;
+        }
+        let pending_token_ids: Vec<TokenId> = match self.level_tokens_per_owner.get(account_id) {
+            Some(level_token_set) => match level_token_set.get(&PENDING_LEVEL) {
+                Some(pending_tokens) => pending_tokens.iter().collect(),
+                None => return 0,
+            },
+            None => return 0,
+        };
+        let random_seed = Base64VecU8::from(env::random_seed());
+        let levels_hidden = self.internal_levels_hidden();
+        let mut revealed = 0;
+        for token_id in pending_token_ids {
+            if revealed >= limit || self.internal_get_remaining_gas() < MAX_RESERVED_WRAP_GAS.0 {
+                break;
+            }
+            let commitment = self.level_commitments.get(&token_id).expect("No level commitment");
+            if env::block_height() < commitment.reveal_height {
+                continue;
+            }
+            let rolled_level = Contract::internal_level_from_seed(&metadata, &random_seed.0, &token_id, commitment.reveal_height);
+            let level = self.internal_capped_level(&metadata, rolled_level);
+            let mut token_metadata = self.token_metadata_by_id.get(&token_id).unwrap();
+            token_metadata.level = level;
+            token_metadata.updated_at = Some(env::block_timestamp_ms());
+            self.token_metadata_by_id.insert(&token_id, &token_metadata);
+            self.internal_move_token_level(account_id, &token_id, &PENDING_LEVEL, &level);
+            self.level_commitments.remove(&token_id);
+            self.level_revealed_at.insert(&token_id, &env::block_height());
+
+            LevelAssigned {
+                owner_id: account_id,
+                token_id: &token_id,
+                level: if levels_hidden { None } else { Some(level) },
+                reveal_height: commitment.reveal_height,
+                random_seed: if levels_hidden { None } else { Some(&random_seed) },
+            }.emit();
+            revealed += 1;
         }
+        revealed
     }
 
     pub(crate) fn internal_wrap_ft_to_nft(&mut self, account_id: &AccountId) {
-        // check white list
-        if self.mint_white_list.contains_key(account_id) {
+        // check white list and accounts that opted out of NFTs, the contract only holds the fee vault
+        if self.mint_white_list.contains_key(account_id) || self.skip_nft.contains_key(account_id) || account_id == &env::current_account_id() {
+            self.internal_set_pending_mints(account_id, 0);
             return;
         }
+        // touching the account fixes the levels that can be revealed
+        self.internal_reveal_levels(account_id, MAX_LEVEL_REVEALS_PER_TOUCH);
         let ft_balance = self.internal_unwrap_balance_of(&account_id);
         let metadata = self.metadata.get().unwrap();
-        let decimal_int = 10u128.pow(metadata.decimals as u32);
-        if ft_balance < decimal_int {
+        let units_per_nft = metadata.units_per_nft.0;
+        // NFTs freed by nft_unwrap stay fungible as long as the balance still holds them
+        let kept = std::cmp::min(self.kept_ft_units.get(account_id).unwrap_or(0), ft_balance / units_per_nft);
+        self.internal_set_kept_ft_units(account_id, kept);
+        if ft_balance < (kept + 1) * units_per_nft {
             // not need to wrap to nft
+            self.internal_set_pending_mints(account_id, 0);
             return;
         }
-        let wrap_count = ft_balance / decimal_int;
-        self.internal_wrap_ft_to_nft_with_count(account_id, &metadata, wrap_count);
+        let wrap_count = ft_balance / units_per_nft - kept;
+        if self.is_paused(PauseSwitch::Wrap) {
+            // keep the whole balance pending, it can be minted with nrc404_process_pending once wrapping resumes
+            self.internal_set_pending_mints(account_id, wrap_count);
+            return;
+        }
+        let minted = self.internal_wrap_ft_to_nft_with_count(account_id, &metadata, wrap_count, true);
+        // record what could not be minted so anyone can finish it with nrc404_process_pending
+        self.internal_set_pending_mints(account_id, wrap_count - minted);
     }
 
     pub(crate) fn internal_handle_protocol_fee(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: u128) -> Balance {
@@ -128,18 +432,25 @@ impl Contract {
         }
         let protocol_fee = amount * self.protocol_fee_rate / PROTOCOL_FEE_DENOMINATOR;
         let amount = amount - protocol_fee;
-        self.protocol_fee += protocol_fee;
         if protocol_fee > 0 {
-            self.internal_transfer_ft(&sender_id, &self.owner_id.clone(), protocol_fee, None);
+            self.internal_collect_fee(&sender_id, protocol_fee, "Protocol fee");
         }
         return amount;
     }
 
-    pub(crate) fn internal_get_new_level(&self, account_id: &AccountId, metadata: &NFTContractMetadata) -> u8 {
+    pub(crate) fn internal_level_from_seed(metadata: &NFTContractMetadata, random_seed: &[u8], token_id: &TokenId, reveal_height: u64) -> u8 {
         if !metadata.enable_random_level {
             return DEFAULT_LEVEL;
         }
-        let random = self.pseudo_random_number(&account_id.to_string(), MAX_LEVEL_PROBABILITY as u64);
+        let mut hasher = Sha256::new();
+        hasher.update(random_seed);
+        hasher.update(token_id.as_bytes());
+        hasher.update(reveal_height.to_le_bytes());
+        let result = hasher.finalize();
+        let mut random_bytes = [0u8; 8];
+        random_bytes.copy_from_slice(&result[0..8]);
+        let random = u64::from_le_bytes(random_bytes) % MAX_LEVEL_PROBABILITY as u64;
+
         let mut added_probability = 0 as u16;
         for (index, probability) in metadata.level_probability.clone().unwrap().iter().enumerate() {
             added_probability += probability;
@@ -150,17 +461,110 @@ impl Contract {
         return DEFAULT_LEVEL;
     }
 
-    pub(crate) fn internal_get_nft_media(&self, metadata: &NFTContractMetadata, mediadata: &NFTMediaData, metadata_token: &TokenMetadata) -> Option<String> {
-        let level_medias = mediadata.level_medias.clone();
+    /// Number of tokens of the level, including the ones waiting in the reserve.
+    pub(crate) fn internal_level_supply(&self, level: u8) -> u64 {
+        self.level_supply.get(&level).unwrap_or(0) + self.reserve_level_supply.get(&level).unwrap_or(0)
+    }
+
+    pub(crate) fn internal_level_cap(metadata: &NFTContractMetadata, level: u8) -> Option<u64> {
+        if level == PENDING_LEVEL {
+            return None;
+        }
+        metadata.level_supply_caps.as_ref().and_then(|level_supply_caps| level_supply_caps.get(level as usize - 1).copied().flatten())
+    }
+
+    pub(crate) fn internal_level_has_room(&self, metadata: &NFTContractMetadata, level: u8) -> bool {
+        match Contract::internal_level_cap(metadata, level) {
+            Some(cap) => self.internal_level_supply(level) < cap,
+            None => true,
+        }
+    }
+
+    /// Step a rolled level down until it reaches a level that isn't capped out.
+    pub(crate) fn internal_capped_level(&self, metadata: &NFTContractMetadata, level: u8) -> u8 {
+        let mut level = level;
+        while level > DEFAULT_LEVEL && !self.internal_level_has_room(metadata, level) {
+            level -= 1;
+        }
+        level
+    }
+
+    /// Index of the token's level in the level medias, None while the level is pending.
+    pub(crate) fn internal_level_index(metadata: &NFTContractMetadata, metadata_token: &TokenMetadata) -> Option<usize> {
+        if metadata_token.level == PENDING_LEVEL {
+            return None;
+        }
         if !metadata.enable_random_level {
-            return Some(level_medias[0].clone());
+            return Some(0);
         }
-        return Some(level_medias[(metadata_token.level - 1) as usize].clone());
+        Some((metadata_token.level - 1) as usize)
+    }
+
+    pub(crate) fn internal_get_nft_media(&self, metadata: &NFTContractMetadata, mediadata: &NFTMediaData, metadata_token: &TokenMetadata) -> Option<String> {
+        let index = Contract::internal_level_index(metadata, metadata_token)?;
+        let level_descriptor = mediadata.level_descriptors.as_ref().map(|level_descriptors| &level_descriptors[index]);
+        level_descriptor.and_then(|level_descriptor| level_descriptor.media.clone())
+            .or_else(|| Some(mediadata.level_medias[index].clone()))
+    }
+
+    /// Whether the tokens still show the placeholder media. The collection is revealed by the
+    /// media manager or once `reveal_at` has passed.
+    pub(crate) fn internal_metadata_hidden(&self, metadata: &NFTContractMetadata, mediadata: &NFTMediaData) -> bool {
+        let reveal_time_passed = metadata.reveal_at.is_some_and(|reveal_at| env::block_timestamp_ms() >= reveal_at);
+        mediadata.placeholder_media.is_some() && !self.metadata_revealed && !reveal_time_passed
+    }
+
+    pub(crate) fn internal_levels_hidden(&self) -> bool {
+        self.internal_metadata_hidden(&self.metadata.get().unwrap(), &self.mediadata.get().unwrap())
     }
 
-    pub(crate) fn internal_mint(&mut self, operator: AccountId, metadata: TokenMetadata, receiver_id: AccountId, perpetual_royalties: Option<HashMap<AccountId, u32>>) {
+    /// Fill in the media and the level descriptor of a token for viewing.
+    /// Fields the token was minted with are kept, only the media always follows the level.
+    /// Before the reveal the level is hidden and the placeholder media is shown instead.
+    pub(crate) fn internal_render_token_metadata(&self, metadata: &NFTContractMetadata, mediadata: &NFTMediaData, token_id: &TokenId, metadata_token: &mut TokenMetadata) {
+        if self.internal_metadata_hidden(metadata, mediadata) {
+            metadata_token.level = PENDING_LEVEL;
+            metadata_token.media = mediadata.placeholder_media.clone();
+            metadata_token.media_hash = None;
+            return;
+        }
+        metadata_token.media = self.internal_get_nft_media(metadata, mediadata, metadata_token);
+        let level_descriptor = match (Contract::internal_level_index(metadata, metadata_token), &mediadata.level_descriptors) {
+            (Some(index), Some(level_descriptors)) => level_descriptors[index].clone(),
+            _ => return,
+        };
+        if metadata_token.title.is_none() {
+            metadata_token.title = level_descriptor.title.map(|title| title
+                .replace("{id}", token_id)
+                .replace("{level}", &metadata_token.level.to_string()));
+        }
+        if level_descriptor.media_hash.is_some() {
+            metadata_token.media_hash = level_descriptor.media_hash;
+        }
+        if metadata_token.description.is_none() {
+            metadata_token.description = level_descriptor.description;
+        }
+        if metadata_token.extra.is_none() {
+            metadata_token.extra = level_descriptor.extra;
+        }
+        if metadata_token.reference.is_none() {
+            metadata_token.reference = level_descriptor.reference;
+            metadata_token.reference_hash = level_descriptor.reference_hash;
+        }
+    }
+
+    pub(crate) fn internal_mint(&mut self, operator: AccountId, metadata: TokenMetadata, receiver_id: AccountId, perpetual_royalties: Option<HashMap<AccountId, u32>>) -> TokenId {
+        let token_id = self.internal_get_and_use_next_nft_id().to_string();
+        self.internal_mint_token(operator, token_id.clone(), metadata, receiver_id, perpetual_royalties, None);
+        token_id
+    }
+
+    pub(crate) fn internal_mint_token(&mut self, operator: AccountId, token_id: TokenId, metadata: TokenMetadata, receiver_id: AccountId, perpetual_royalties: Option<HashMap<AccountId, u32>>, memo: Option<String>) {
         let nft_metadata = self.metadata.get().unwrap();
-        require!(metadata.level != 0 && metadata.level <= nft_metadata.max_level, INVALID_LEVEL);
+        require!(metadata.level <= nft_metadata.max_level, INVALID_LEVEL);
+        require!(self.internal_level_has_room(&nft_metadata, metadata.level), LEVEL_CAP_REACHED);
+        let denomination = metadata.denomination.unwrap_or(1);
+        require!(Contract::internal_denominations(&nft_metadata).contains(&denomination), INVALID_DENOMINATION);
 
         // create a royalty map to store in the token
         let mut royalty = HashMap::new();
@@ -169,6 +573,8 @@ impl Contract {
         if let Some(perpetual_royalties) = perpetual_royalties {
             //make sure that the length of the perpetual royalties is below 7 since we won't have enough GAS to pay out that many people
             assert!(perpetual_royalties.len() < 7, "Cannot add more than 6 perpetual royalty amounts");
+            //the owner gets 100% - total perpetual royalties, so they can't add up to more than 100%
+            assert_valid_royalty(&perpetual_royalties);
 
             //iterate through the perpetual royalties and insert the account and amount in the royalty map
             for (account, amount) in perpetual_royalties {
@@ -188,17 +594,32 @@ impl Contract {
             royalty,
         };
 
-        // cost 1 token
-        self.internal_withdraw(&operator, 10u128.pow(nft_metadata.decimals as u32));
+        // cost the units of the note
+        let amount = U128(denomination as u128 * nft_metadata.units_per_nft.0);
+        self.internal_withdraw(&operator, amount.0);
+        FtBurn {
+            owner_id: &operator,
+            amount: &amount,
+            memo: Some(FT_TO_NFT_MEMO),
+        }.emit();
 
-        let token_id = self.internal_get_and_use_next_nft_id().to_string();
         self.tokens_by_id.insert(&token_id, &token);
+        if denomination > 1 {
+            self.token_denominations.insert(&token_id, &denomination);
+            NoteMint {
+                owner_id: &token.owner_id,
+                token_id: &token_id,
+                denomination,
+            }.emit();
+        }
 
         //insert the token ID and metadata
         self.token_metadata_by_id.insert(&token_id, &metadata);
 
         //call the internal method for adding the token to the owner
         self.internal_add_token_to_owner(&token.owner_id, &token_id, &metadata.level);
+        //the new token is paid by the owner's storage balance
+        self.internal_storage_charge(&token.owner_id, self.bytes_per_nft);
 
         // Construct the mint log as per the events standard.
         let nft_mint_log: EventLog = EventLog {
@@ -213,7 +634,7 @@ impl Contract {
                 // Vector of token IDs that were minted.
                 token_ids: vec![token_id.to_string()],
                 // An optional memo to include.
-                memo: None,
+                memo,
             }]),
         };
 
@@ -227,41 +648,4 @@ impl Contract {
         (prepaid_gas - used_gas).0
     }
 
-    pub fn pseudo_random_number(&self, account_id: &str, n: u64) -> u64 {
-        let block_index = env::block_height();
-        let block_timestamp = env::block_timestamp();
-        let account_balance = env::account_balance();
-        let epoch_height = env::epoch_height();
-        let random_seed = env::random_seed();
-        let used_gas = env::used_gas();
-
-        let mut hasher = Sha256::new();
-
-        hasher.update(block_index.to_le_bytes());
-        hasher.update(block_timestamp.to_le_bytes());
-        hasher.update(account_balance.to_le_bytes());
-        hasher.update(epoch_height.to_le_bytes());
-        hasher.update(&random_seed);
-        hasher.update(used_gas.0.to_le_bytes());
-        hasher.update(account_id.as_bytes());
-
-        let result = hasher.finalize();
-        let hash_number = u64::from_le_bytes([
-            result[0].clone(), result[1].clone(), result[2].clone(), result[3].clone(),
-            result[4].clone(), result[5].clone(), result[6].clone(), result[7].clone(),
-        ]);
-
-        let combined = block_index
-            .wrapping_add(block_timestamp)
-            .wrapping_add(hash_number)
-            .wrapping_add(epoch_height)
-            .wrapping_add(used_gas.0);
-        let balance_as_u64 = (account_balance % u128::from(u64::MAX)) as u64;
-        let combined_with_balance = combined.wrapping_add(balance_as_u64);
-        let complex_hash = combined_with_balance.wrapping_mul(0x4cd6944c5e2e53a9);
-
-        let random_number = complex_hash % n;
-        random_number
-    }
-
 }
@@ -1,12 +1,94 @@
 use near_sdk::{assert_one_yocto, require};
 use crate::*;
 
+/// Named roles for the admin methods. The owner implicitly holds every role.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    FeeManager,
+    MediaManager,
+    Pauser,
+    Upgrader,
+    Operator,
+}
+
+/// Contract metadata of the first release, before `units_per_nft`.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct OldNFTContractMetadata {
+    spec: String,
+    ft_spec: String,
+    name: String,
+    symbol: String,
+    decimals: u8,
+    icon: Option<String>,
+    base_uri: Option<String>,
+    reference: Option<String>,
+    reference_hash: Option<Base64VecU8>,
+    max_level: u8,
+    enable_random_level: bool,
+    level_probability: Option<Vec<u16>>,
+}
+
+/// Level medias of the first release, before the level descriptors.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct OldNFTMediaData {
+    level_medias: Vec<String>,
+}
+
+/// Contract state of the first release.
+#[derive(BorshDeserialize)]
+struct OldContract {
+    owner_id: AccountId,
+    operator: AccountId,
+    protocol_fee: u128,
+    protocol_fee_rate: u128,
+    #[allow(dead_code)]
+    tokens_per_owner: LookupMap<AccountId, UnorderedSet<TokenId>>,
+    #[allow(dead_code)]
+    level_tokens_per_owner: LookupMap<AccountId, LookupMap<u8, UnorderedSet<TokenId>>>,
+    #[allow(dead_code)]
+    tokens_by_id: LookupMap<TokenId, Token>,
+    token_metadata_by_id: UnorderedMap<TokenId, TokenMetadata>,
+    metadata: LazyOption<OldNFTContractMetadata>,
+    mediadata: LazyOption<OldNFTMediaData>,
+    next_nft_id: u128,
+    #[allow(dead_code)]
+    accounts: LookupMap<AccountId, Balance>,
+    total_supply: Balance,
+    bytes_for_longest_account_id: StorageUsage,
+    #[allow(dead_code)]
+    mint_white_list: LookupMap<AccountId, bool>,
+    #[allow(dead_code)]
+    fee_white_list: LookupMap<AccountId, bool>,
+}
+
 #[near_bindgen]
 impl Contract {
-    /// Change owner. Only can be called by owner.
-    pub fn set_owner(&mut self, owner_id: AccountId) {
+    /// Propose a new owner. Ownership only moves once the proposed account calls accept_owner.
+    #[payable]
+    pub fn propose_owner(&mut self, owner_id: AccountId) {
         self.assert_owner();
-        self.owner_id = owner_id.clone();
+        self.pending_owner_id = Some(owner_id.clone());
+        OwnershipProposed {
+            owner_id: &self.owner_id,
+            pending_owner_id: &owner_id,
+        }.emit();
+    }
+
+    /// Accept the ownership proposed to the caller.
+    #[payable]
+    pub fn accept_owner(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        require!(self.pending_owner_id.as_ref() == Some(&account_id), ERR_NOT_ALLOWED);
+        let old_owner_id = std::mem::replace(&mut self.owner_id, account_id);
+        self.pending_owner_id = None;
+        OwnershipTransferred {
+            old_owner_id: &old_owner_id,
+            new_owner_id: &self.owner_id,
+        }.emit();
     }
 
     /// Get the owner of this account.
@@ -14,43 +96,265 @@ impl Contract {
         self.owner_id.clone()
     }
 
+    /// Get the account that was proposed as the next owner, if any.
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner_id.clone()
+    }
+
     pub(crate) fn assert_owner(&self) {
         assert_one_yocto();
         require!(env::predecessor_account_id() == self.owner_id, ERR_NOT_ALLOWED);
     }
 
+    pub(crate) fn assert_indexes_backfilled(&self) {
+        require!(self.index_backfill_cursor.is_none(), INDEXES_NOT_BACKFILLED);
+    }
+
+    pub(crate) fn assert_role(&self, role: Role) {
+        assert_one_yocto();
+        require!(self.internal_has_role(&env::predecessor_account_id(), role), ERR_NOT_ALLOWED);
+    }
+
+    pub(crate) fn internal_has_role(&self, account_id: &AccountId, role: Role) -> bool {
+        account_id == &self.owner_id || self.roles.get(account_id).is_some_and(|roles| roles.contains(&role))
+    }
+
     pub fn is_owner(&self) -> bool {
         return env::predecessor_account_id() == self.owner_id;
     }
 
+    /// Grant a role to an account. Admins can grant every role but admin, which only the owner can grant.
+    #[payable]
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        self.assert_role_manager(role);
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        if !roles.contains(&role) {
+            roles.push(role);
+            self.roles.insert(&account_id, &roles);
+            RoleGranted {
+                account_id: &account_id,
+                role,
+                sender_id: &env::predecessor_account_id(),
+            }.emit();
+        }
+    }
+
+    /// Revoke a role from an account. Admins can revoke every role but admin, which only the owner can revoke.
+    #[payable]
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) {
+        self.assert_role_manager(role);
+        self.internal_revoke_role(&account_id, role);
+    }
+
+    /// Give up a role held by the caller.
+    #[payable]
+    pub fn renounce_role(&mut self, role: Role) {
+        assert_one_yocto();
+        self.internal_revoke_role(&env::predecessor_account_id(), role);
+    }
+
+    fn assert_role_manager(&self, role: Role) {
+        if role == Role::Admin {
+            self.assert_owner();
+        } else {
+            self.assert_role(Role::Admin);
+        }
+    }
+
+    fn internal_revoke_role(&mut self, account_id: &AccountId, role: Role) {
+        let mut roles = self.roles.get(account_id).unwrap_or_default();
+        if roles.contains(&role) {
+            roles.retain(|r| r != &role);
+            if roles.is_empty() {
+                self.roles.remove(account_id);
+            } else {
+                self.roles.insert(account_id, &roles);
+            }
+            RoleRevoked {
+                account_id,
+                role,
+                sender_id: &env::predecessor_account_id(),
+            }.emit();
+        }
+    }
+
+    /// Whether the account holds the role, the owner holds every role.
+    pub fn has_role(&self, account_id: AccountId, role: Role) -> bool {
+        self.internal_has_role(&account_id, role)
+    }
+
+    /// Get the roles granted to an account.
+    pub fn roles_of(&self, account_id: AccountId) -> Vec<Role> {
+        self.roles.get(&account_id).unwrap_or_default()
+    }
+
+    /// Query for the accounts holding a role using pagination.
+    pub fn role_members(&self, role: Role, from_index: Option<U128>, limit: Option<u64>) -> Vec<AccountId> {
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        self.roles.iter()
+            .filter(|(_, roles)| roles.contains(&role))
+            //skip to the index we specified in the start variable
+            .skip(start as usize)
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            .map(|(account_id, _)| account_id)
+            .collect()
+    }
+
     #[payable]
     pub fn set_operator(&mut self, new_operator: AccountId) {
-        self.assert_owner();
+        self.assert_role(Role::Admin);
+        OperatorChanged {
+            old_operator_id: &self.operator,
+            new_operator_id: &new_operator,
+        }.emit();
         self.operator = new_operator;
     }
 
     #[payable]
     pub fn set_protocol_fee_rate(&mut self, new_protocol_fee_rate: U128) {
-        self.assert_owner();
+        self.assert_role(Role::FeeManager);
+        require!(new_protocol_fee_rate.0 <= MAX_PROTOCOL_FEE_RATE, INVALID_PROTOCOL_FEE_RATE);
         self.protocol_fee_rate = new_protocol_fee_rate.0;
     }
 
+    /// Change the account receiving the treasury share of the protocol fees.
+    #[payable]
+    pub fn set_treasury(&mut self, treasury_id: AccountId) {
+        self.assert_role(Role::FeeManager);
+        self.treasury_id = treasury_id;
+    }
+
+    /// Change how the protocol fees are split, shares are in basis points and must add up to 10000.
+    #[payable]
+    pub fn set_fee_splits(&mut self, fee_splits: Vec<FeeSplit>) {
+        self.assert_role(Role::FeeManager);
+        require!(!fee_splits.is_empty() && fee_splits.len() <= MAX_FEE_SPLITS, INVALID_FEE_SPLITS);
+        let total_share: u32 = fee_splits.iter().map(|fee_split| fee_split.share as u32).sum();
+        require!(total_share == FEE_SPLIT_DENOMINATOR as u32, INVALID_FEE_SPLITS);
+        self.fee_splits = fee_splits;
+    }
+
+    /// Set the royalty paid by tokens minted without one, for one level or for the whole collection.
+    /// An empty royalty for a level removes its override. Payouts always use the current defaults.
+    #[payable]
+    pub fn set_default_royalty(&mut self, royalty: HashMap<AccountId, u32>, level: Option<u8>) {
+        self.assert_role(Role::FeeManager);
+        //same limit as the perpetual royalties of a minted token, there isn't enough GAS to pay out more people
+        require!(royalty.len() < 7, INVALID_ROYALTY);
+        assert_valid_royalty(&royalty);
+        match level {
+            Some(level) => {
+                let metadata = self.metadata.get().unwrap();
+                require!(level != PENDING_LEVEL && level <= metadata.max_level, INVALID_LEVEL);
+                if royalty.is_empty() {
+                    self.level_royalties.remove(&level);
+                } else {
+                    self.level_royalties.insert(&level, &royalty);
+                }
+            }
+            None => self.default_royalty = royalty,
+        }
+    }
+
+    /// Get the default royalty of a level, or of the whole collection when no level is given.
+    /// Every level pays the collection default while the levels are hidden.
+    pub fn get_default_royalty(&self, level: Option<u8>) -> HashMap<AccountId, u32> {
+        match level {
+            //tokens pay the collection default until the levels are revealed
+            Some(_) if self.internal_levels_hidden() => self.internal_effective_royalty(&HashMap::new(), PENDING_LEVEL),
+            Some(level) => self.internal_effective_royalty(&HashMap::new(), level),
+            None => self.default_royalty.clone(),
+        }
+    }
+
+    /// Set the total royalty in basis points paid by tokens of `level` minted without one, at most
+    /// MAX_LEVEL_ROYALTY_RATE. The default recipients keep their split of it. None removes the rate.
+    #[payable]
+    pub fn set_level_royalty_rate(&mut self, level: u8, rate: Option<u32>) {
+        self.assert_role(Role::FeeManager);
+        let metadata = self.metadata.get().unwrap();
+        require!(level != PENDING_LEVEL && level <= metadata.max_level, INVALID_LEVEL);
+        match rate {
+            Some(rate) => {
+                require!(rate <= MAX_LEVEL_ROYALTY_RATE, INVALID_ROYALTY);
+                self.level_royalty_rates.insert(&level, &rate);
+            }
+            None => {
+                self.level_royalty_rates.remove(&level);
+            }
+        }
+    }
+
+    /// Get the total royalty in basis points set for a level. None while the levels are hidden,
+    /// all tokens pay the collection default until the reveal.
+    pub fn get_level_royalty_rate(&self, level: u8) -> Option<u32> {
+        if self.internal_levels_hidden() {
+            return None;
+        }
+        self.level_royalty_rates.get(&level)
+    }
+
     #[payable]
     pub fn set_mint_white(&mut self, account: AccountId, enable: bool) {
-        self.assert_owner();
-        self.mint_white_list.insert(&account, &enable);
+        self.assert_role(Role::Admin);
+        //the white list is checked by key, so disabling has to remove the account
+        if enable {
+            self.mint_white_list.insert(&account, &true);
+        } else {
+            self.mint_white_list.remove(&account);
+        }
+        WhitelistUpdated {
+            list: "mint",
+            account_id: &account,
+            enabled: enable,
+        }.emit();
     }
 
     #[payable]
     pub fn set_fee_white(&mut self, account: AccountId, enable: bool) {
-        self.assert_owner();
-        self.fee_white_list.insert(&account, &enable);
+        self.assert_role(Role::FeeManager);
+        //the white list is checked by key, so disabling has to remove the account
+        if enable {
+            self.fee_white_list.insert(&account, &true);
+        } else {
+            self.fee_white_list.remove(&account);
+        }
+        WhitelistUpdated {
+            list: "fee",
+            account_id: &account,
+            enabled: enable,
+        }.emit();
+    }
+
+    /// Set how many tokens of `level` are fused into one token of the next level. 0 disables fusion for the level.
+    #[payable]
+    pub fn set_fusion_cost(&mut self, level: u8, count: u32) {
+        self.assert_role(Role::Admin);
+        let metadata = self.metadata.get().unwrap();
+        require!(level != PENDING_LEVEL && level < metadata.max_level, INVALID_LEVEL);
+        if count == 0 {
+            self.fusion_costs.remove(&level);
+        } else {
+            require!(count >= 2, INVALID_FUSION_COUNT);
+            self.fusion_costs.insert(&level, &count);
+        }
+    }
+
+    /// Set the price and cooldown of level rerolls. None disables rerolls.
+    #[payable]
+    pub fn set_reroll_config(&mut self, reroll_config: Option<RerollConfig>) {
+        self.assert_role(Role::Admin);
+        self.reroll_config = reroll_config;
     }
 
     #[payable]
     pub fn update_media(&mut self, ft_icon: String, mediadata: NFTMediaData) {
-        self.assert_owner();
+        self.assert_role(Role::MediaManager);
         let mut metadata = self.metadata.get().unwrap();
+        Contract::internal_check_contract_meta_data(&metadata, &mediadata);
         metadata.icon = Some(ft_icon);
         self.metadata = LazyOption::new(
             StorageKey::NFTContractMetadata.try_to_vec().unwrap(),
@@ -61,17 +365,155 @@ impl Contract {
             StorageKey::NFTMediaData.try_to_vec().unwrap(),
             Some(&mediadata),
         );
+        MediaUpdated {
+            sender_id: &env::predecessor_account_id(),
+        }.emit();
+    }
+
+    /// Show the levels and level media of all tokens instead of the placeholder media, ahead of `reveal_at`.
+    /// Indexers are then told about it page by page with `nrc404_announce_reveal`.
+    #[payable]
+    pub fn nrc404_reveal_collection(&mut self) {
+        self.assert_role(Role::MediaManager);
+        require!(!self.metadata_revealed, ALREADY_REVEALED);
+        self.metadata_revealed = true;
+    }
+
+    /// Once the collection is revealed, tell indexers to refresh the token IDs from `from_index` up to `limit` of them,
+    /// in nft_metadata_update events of at most `MAX_METADATA_UPDATE_TOKENS` IDs. Call it page by page up to `next_nft_id`.
+    #[payable]
+    pub fn nrc404_announce_reveal(&mut self, from_index: Option<U128>, limit: Option<u64>) {
+        self.assert_role(Role::MediaManager);
+        require!(!self.internal_levels_hidden(), LEVELS_HIDDEN);
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let mut from_id = u128::from(from_index.unwrap_or(U128(0)));
+        let end_id = std::cmp::min(from_id + limit.map_or(MAX_METADATA_UPDATE_TOKENS, u128::from), self.next_nft_id);
+        while from_id < end_id {
+            let to_id = std::cmp::min(from_id + MAX_METADATA_UPDATE_TOKENS, end_id);
+            // Construct the metadata update log as per the events standard.
+            let nft_metadata_update_log: EventLog = EventLog {
+                // Standard name ("nep171").
+                standard: NFT_STANDARD_NAME.to_string(),
+                // Version of the standard ("1.1.0").
+                version: NFT_METADATA_UPDATE_SPEC.to_string(),
+                // The data related with the event stored in a vector.
+                event: EventLogVariant::NftMetadataUpdate(vec![NftMetadataUpdateLog {
+                    // Vector of token IDs whose metadata changed.
+                    token_ids: (from_id..to_id).map(|token_id| token_id.to_string()).collect(),
+                    // An optional memo to include.
+                    memo: Some(REVEAL_MEMO.to_string()),
+                }]),
+            };
+
+            // Log the serialized json.
+            env::log_str(&nft_metadata_update_log.to_string());
+            from_id = to_id;
+        }
+    }
+
+    /// Whether the tokens show their levels and level media.
+    pub fn nrc404_is_revealed(&self) -> bool {
+        !self.internal_levels_hidden()
+    }
+
+    /// Count up to `limit` tokens migrated from the first release into the level supplies, holders and
+    /// storage charges, and return whether every token is counted. Call it until it returns true,
+    /// NFTs can not be minted, moved or burned, and storage can not be withdrawn, before that.
+    /// The storage of the migrated tokens was already paid for, so it is credited to their owners as well.
+    #[payable]
+    pub fn nrc404_backfill_indexes(&mut self, limit: Option<u64>) -> bool {
+        self.assert_owner();
+        let from_index = match self.index_backfill_cursor {
+            Some(from_index) => from_index,
+            None => return true,
+        };
+        let token_count = self.token_metadata_by_id.len();
+        let to_index = std::cmp::min(from_index + limit.unwrap_or(50), token_count);
+        let token_ids: Vec<TokenId> = (from_index..to_index).map(|index| self.token_metadata_by_id.keys_as_vector().get(index).unwrap()).collect();
+        for token_id in token_ids {
+            let token = self.tokens_by_id.get(&token_id).expect("No token");
+            let level = self.token_metadata_by_id.get(&token_id).unwrap().level;
+            let level_supply = self.level_supply.get(&level).unwrap_or(0);
+            self.level_supply.insert(&level, &(level_supply + 1));
+            self.holders.insert(&token.owner_id);
+            //the first release kept no receive order, the order of the token map stands in for it
+            self.internal_add_token_to_receive_order(&token.owner_id, &token_id);
+            let approval_bytes: StorageUsage = token.approved_account_ids.keys().map(bytes_for_approved_account_id).sum();
+            self.internal_storage_charge_migrated(&token.owner_id, self.bytes_per_nft + approval_bytes);
+        }
+        self.index_backfill_cursor = if to_index < token_count { Some(to_index) } else { None };
+        self.index_backfill_cursor.is_none()
+    }
+
+    /// Add accounts that only held FT at the migration to the holders, once the token indexes are backfilled.
+    /// Accounts without a balance are skipped. Registered accounts get the registration they paid
+    /// for in the first release credited to their storage balance.
+    #[payable]
+    pub fn nrc404_backfill_holders(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        self.assert_indexes_backfilled();
+        for account_id in account_ids {
+            if self.accounts.contains_key(&account_id) {
+                self.internal_storage_charge_migrated(&account_id, 0);
+            }
+            self.internal_update_holder(&account_id);
+        }
     }
 
     /// Should only be called by this contract on migration.
-    /// This is NOOP implementation. KEEP IT if you haven't changed contract state.
-    /// If you have, you need to implement migration from old state
-    /// (keep the old struct with different name to deserialize it first).
-    /// After migration goes live, revert back to this implementation for next updates.
+    /// Migrates the state of the first release, where one NFT always stood for 10^decimals of FT,
+    /// to the current layout. The collections keep their prefixes, the contract metadata gets
+    /// `units_per_nft` and the new settings start from the defaults of `new`.
+    /// The first release had no reserve. The level supplies, holders and storage charges of the
+    /// migrated tokens are rebuilt afterwards with `nrc404_backfill_indexes` and `nrc404_backfill_holders`.
+    /// After migration goes live, revert back to the NOOP implementation for next updates.
     #[init(ignore_state)]
     #[private]
     pub fn migrate() -> Self {
-        let contract: Contract = env::state_read().expect(CAN_NOT_READ_STATE);
+        let old: OldContract = env::state_read().expect(CAN_NOT_READ_STATE);
+        let old_metadata = old.metadata.get().unwrap();
+        let metadata = NFTContractMetadata {
+            spec: old_metadata.spec,
+            ft_spec: old_metadata.ft_spec,
+            name: old_metadata.name,
+            symbol: old_metadata.symbol,
+            decimals: old_metadata.decimals,
+            units_per_nft: U128(10u128.pow(old_metadata.decimals as u32)),
+            icon: old_metadata.icon,
+            base_uri: old_metadata.base_uri,
+            reference: old_metadata.reference,
+            reference_hash: old_metadata.reference_hash,
+            max_level: old_metadata.max_level,
+            enable_random_level: old_metadata.enable_random_level,
+            level_probability: old_metadata.level_probability,
+            level_supply_caps: None,
+            reveal_at: None,
+            denominations: None,
+        };
+
+        let mediadata = NFTMediaData {
+            level_medias: old.mediadata.get().unwrap().level_medias,
+            level_descriptors: None,
+            placeholder_media: None,
+        };
+
+        let mut contract = Contract::internal_new_state(&old.owner_id, &metadata, &mediadata, old.total_supply);
+        contract.operator = old.operator;
+        contract.protocol_fee = old.protocol_fee;
+        contract.protocol_fee_rate = old.protocol_fee_rate;
+        //the length of the token metadata map is kept in the contract state
+        contract.token_metadata_by_id = old.token_metadata_by_id;
+        contract.next_nft_id = old.next_nft_id;
+        contract.bytes_for_longest_account_id = old.bytes_for_longest_account_id;
+        contract.measure_bytes_per_nft();
+        if !contract.token_metadata_by_id.is_empty() {
+            contract.index_backfill_cursor = Some(0);
+        }
+
+        // Register the contract account, it holds the protocol fees until they are claimed.
+        if !contract.accounts.contains_key(&env::current_account_id()) {
+            contract.internal_register_account(&env::current_account_id());
+        }
         contract
     }
 }
@@ -92,7 +534,7 @@ mod upgrade {
     pub extern "C" fn upgrade() {
         env::setup_panic_hook();
         let contract: Contract = env::state_read().expect("ERR_CONTRACT_IS_NOT_INITIALIZED");
-        contract.assert_owner();
+        contract.assert_role(Role::Upgrader);
         let current_account_id = env::current_account_id().as_bytes().to_vec();
         let migrate_method_name = b"migrate".to_vec();
         let nft_total_supply_method_name = b"nft_total_supply".to_vec();
@@ -0,0 +1,69 @@
+use near_sdk::require;
+use crate::*;
+
+/// Parts of the contract that can be paused independently.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum PauseSwitch {
+    /// ft_transfer and ft_transfer_call
+    FtTransfer,
+    /// nft_transfer, nft_transfer_call and nft_transfer_payout
+    NftTransfer,
+    /// nft_wrap and the automatic minting of NFTs out of FT balances
+    Wrap,
+    /// nft_wrap_by_operator
+    OperatorWrap,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Pause the given switches. Only callable by a pauser.
+    #[payable]
+    pub fn nrc404_pause(&mut self, switches: Vec<PauseSwitch>) {
+        self.assert_role(Role::Pauser);
+        let switches: Vec<PauseSwitch> = switches.into_iter().filter(|switch| !self.paused.contains(switch)).collect();
+        if switches.is_empty() {
+            return;
+        }
+        self.paused.extend(switches.iter());
+        Paused {
+            account_id: &env::predecessor_account_id(),
+            switches: &switches,
+        }.emit();
+    }
+
+    /// Unpause the given switches. Only callable by a pauser.
+    #[payable]
+    pub fn nrc404_unpause(&mut self, switches: Vec<PauseSwitch>) {
+        self.assert_role(Role::Pauser);
+        let switches: Vec<PauseSwitch> = switches.into_iter().filter(|switch| self.paused.contains(switch)).collect();
+        if switches.is_empty() {
+            return;
+        }
+        self.paused.retain(|switch| !switches.contains(switch));
+        Unpaused {
+            account_id: &env::predecessor_account_id(),
+            switches: &switches,
+        }.emit();
+    }
+
+    /// Get the switches that are currently paused.
+    pub fn nrc404_paused(&self) -> Vec<PauseSwitch> {
+        self.paused.clone()
+    }
+
+    pub(crate) fn is_paused(&self, switch: PauseSwitch) -> bool {
+        self.paused.contains(&switch)
+    }
+
+    pub(crate) fn assert_not_paused(&self, switch: PauseSwitch) {
+        let err = match switch {
+            PauseSwitch::FtTransfer => FT_TRANSFER_PAUSED,
+            PauseSwitch::NftTransfer => NFT_TRANSFER_PAUSED,
+            PauseSwitch::Wrap => WRAP_PAUSED,
+            PauseSwitch::OperatorWrap => OPERATOR_WRAP_PAUSED,
+        };
+        require!(!self.is_paused(switch), err);
+    }
+}
@@ -0,0 +1,63 @@
+use near_sdk::require;
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Pay the reroll price to roll the level of an owned token again.
+    /// The token goes back to pending and its new level is revealed like a freshly minted one.
+    #[payable]
+    pub fn nrc404_reroll(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let metadata = self.metadata.get().unwrap();
+        require!(metadata.enable_random_level, REROLL_DISABLED);
+        require!(!self.internal_levels_hidden(), LEVELS_HIDDEN);
+        let reroll_config = self.reroll_config.clone().expect(REROLL_DISABLED);
+
+        let token = self.tokens_by_id.get(&token_id).expect("No token");
+        require!(token.owner_id == account_id, "Predecessor must be the token owner.");
+        let mut token_metadata = self.token_metadata_by_id.get(&token_id).unwrap();
+        require!(token_metadata.level != PENDING_LEVEL, INVALID_LEVEL);
+        if let Some(last_reroll_at) = self.last_reroll_at.get(&token_id) {
+            require!(env::block_timestamp_ms() >= last_reroll_at + reroll_config.cooldown_ms, REROLL_COOLDOWN);
+        }
+
+        let price = reroll_config.price.0;
+        if price > 0 {
+            let ft_balance = self.internal_unwrap_balance_of(&account_id);
+            if ft_balance < price {
+                // the rerolled token must not be burned to pay for its own reroll
+                let was_locked = self.locked_tokens.contains_key(&token_id);
+                self.locked_tokens.insert(&token_id, &true);
+                self.internal_wrap_nft_to_ft(&account_id, price - ft_balance, true);
+                if !was_locked {
+                    self.locked_tokens.remove(&token_id);
+                }
+            }
+            if reroll_config.burn {
+                self.internal_withdraw(&account_id, price);
+                self.total_supply -= price;
+                FtBurn {
+                    owner_id: &account_id,
+                    amount: &reroll_config.price,
+                    memo: Some("Level reroll"),
+                }.emit();
+            } else {
+                self.internal_collect_fee(&account_id, price, "Level reroll");
+            }
+        }
+
+        self.internal_move_token_level(&account_id, &token_id, &token_metadata.level, &PENDING_LEVEL);
+        token_metadata.level = PENDING_LEVEL;
+        token_metadata.updated_at = Some(env::block_timestamp_ms());
+        self.token_metadata_by_id.insert(&token_id, &token_metadata);
+        self.level_revealed_at.remove(&token_id);
+        self.internal_commit_level(&account_id, &token_id);
+        self.last_reroll_at.insert(&token_id, &env::block_timestamp_ms());
+    }
+
+    /// Get the price and cooldown of level rerolls.
+    pub fn nrc404_reroll_config(&self) -> Option<RerollConfig> {
+        self.reroll_config.clone()
+    }
+}
@@ -1,3 +1,4 @@
+use near_sdk::require;
 use crate::*;
 
 pub trait NonFungibleTokenCore {
@@ -34,8 +35,9 @@ impl NonFungibleTokenCore for Contract {
         let mut payout_object = Payout {
             payout: HashMap::new()
         };
-        //get the royalty object from token
-		let royalty = token.royalty;
+        //get the royalty object from token, falling back to the collection defaults
+		let level = self.internal_royalty_level(&token_id);
+		let royalty = self.internal_effective_royalty(&token.royalty, level);
 
         //make sure we're not paying out to too many people (GAS limits this)
 		assert!(royalty.len() as u32 <= max_len_payout, "Market cannot payout to that many receivers");
@@ -53,7 +55,8 @@ impl NonFungibleTokenCore for Contract {
 		}
 
 		// payout to previous owner who gets 100% - total perpetual royalties
-		payout_object.payout.insert(owner_id, royalty_to_payout(10000 - total_perpetual, balance_u128));
+		require!(total_perpetual <= ROYALTY_DENOMINATOR, INVALID_ROYALTY);
+		payout_object.payout.insert(owner_id, royalty_to_payout(ROYALTY_DENOMINATOR - total_perpetual, balance_u128));
 
         //return the payout object
 		payout_object
@@ -74,6 +77,10 @@ impl NonFungibleTokenCore for Contract {
         assert_one_yocto();
         //get the sender ID
         let sender_id = env::predecessor_account_id();
+        //only nft_transfer_call redeems a token sent to the contract, anywhere else it would be stranded
+        require!(receiver_id != env::current_account_id(), NFT_TO_CONTRACT);
+        //the level doesn't change with the transfer, it picks the default royalty if the token has none
+        let level = self.internal_royalty_level(&token_id);
         //transfer the token to the passed in receiver and get the previous token object back
         let previous_token = self.internal_transfer(
             &sender_id,
@@ -84,8 +91,8 @@ impl NonFungibleTokenCore for Contract {
         );
 
         //refund the previous token owner for the storage used up by the previous approved account IDs
-        refund_approved_account_ids(
-            previous_token.owner_id.clone(),
+        self.refund_approved_account_ids(
+            &previous_token.owner_id,
             &previous_token.approved_account_ids,
         );
 
@@ -99,8 +106,8 @@ impl NonFungibleTokenCore for Contract {
         let mut payout_object = Payout {
             payout: HashMap::new()
         };
-        //get the royalty object from token
-		let royalty = previous_token.royalty;
+        //get the royalty object from token, falling back to the collection defaults
+		let royalty = self.internal_effective_royalty(&previous_token.royalty, level);
 
         //make sure we're not paying out to too many people (GAS limits this)
 		assert!(royalty.len() as u32 <= max_len_payout, "Market cannot payout to that many receivers");
@@ -118,9 +125,45 @@ impl NonFungibleTokenCore for Contract {
 		}
 
 		// payout to previous owner who gets 100% - total perpetual royalties
-		payout_object.payout.insert(owner_id, royalty_to_payout(10000 - total_perpetual, balance_u128));
+		require!(total_perpetual <= ROYALTY_DENOMINATOR, INVALID_ROYALTY);
+		payout_object.payout.insert(owner_id, royalty_to_payout(ROYALTY_DENOMINATOR - total_perpetual, balance_u128));
 
         //return the payout object
 		payout_object
     }
-}
\ No newline at end of file
+}
+#[near_bindgen]
+impl Contract {
+    //get the basis point shares a sale of the token pays out, the owner gets what the royalties leave
+    pub fn nft_royalty_info(&self, token_id: TokenId) -> HashMap<AccountId, u32> {
+        let token = self.tokens_by_id.get(&token_id).expect("No token");
+        let level = self.internal_royalty_level(&token_id);
+        let mut royalty = self.internal_effective_royalty(&token.royalty, level);
+        //the owner's own share is part of what they get anyway
+        royalty.remove(&token.owner_id);
+        let total_perpetual: u32 = royalty.values().sum();
+        require!(total_perpetual <= ROYALTY_DENOMINATOR, INVALID_ROYALTY);
+        royalty.insert(token.owner_id, ROYALTY_DENOMINATOR - total_perpetual);
+        royalty
+    }
+
+    //move the royalty share of the caller to another account, for every token. None moves it back to the caller.
+    #[payable]
+    pub fn nrc404_set_royalty_recipient(&mut self, recipient_id: Option<AccountId>) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        match recipient_id {
+            Some(recipient_id) if recipient_id != account_id => {
+                self.royalty_recipients.insert(&account_id, &recipient_id);
+            }
+            _ => {
+                self.royalty_recipients.remove(&account_id);
+            }
+        }
+    }
+
+    //get the account the royalty share of an account is paid to
+    pub fn nrc404_royalty_recipient_of(&self, account_id: AccountId) -> AccountId {
+        self.royalty_recipients.get(&account_id).unwrap_or(account_id)
+    }
+}
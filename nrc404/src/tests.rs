@@ -1,8 +1,8 @@
 /* unit tests */
 #[cfg(test)]
-use crate::Contract;
-use crate::TokenMetadata;
+use crate::{Contract, LevelDescriptor, NFTContractMetadata, NFTMediaData, TokenMetadata, DEFAULT_LEVEL, LEVEL_REVEAL_DELAY_BLOCKS, PENDING_LEVEL};
 use crate::approval::NonFungibleTokenCore;
+use crate::nep141_storage::StorageManagement;
 use near_sdk::json_types::{U128, U64};
 use near_sdk::test_utils::{accounts, VMContextBuilder};
 use near_sdk::testing_env;
@@ -12,6 +12,8 @@ use std::collections::HashMap;
 
 const MINT_STORAGE_COST: u128 = 100_000_000_000_000_000_000_000;
 const MIN_REQUIRED_APPROVAL_YOCTO: u128 = 170000000000000000000;
+/// FT amount one NFT stands for in the sample contract
+const UNIT: u128 = 100;
 
 fn get_context(predecessor: AccountId) -> VMContextBuilder {
     let mut builder = VMContextBuilder::new();
@@ -19,8 +21,72 @@ fn get_context(predecessor: AccountId) -> VMContextBuilder {
     builder
 }
 
+fn sample_contract_metadata() -> NFTContractMetadata {
+    NFTContractMetadata {
+        spec: "nft-1.0.0".to_string(),
+        ft_spec: "ft-1.0.0".to_string(),
+        name: "NRC404".to_string(),
+        symbol: "NRC".to_string(),
+        decimals: 2,
+        units_per_nft: U128(UNIT),
+        icon: None,
+        base_uri: None,
+        reference: None,
+        reference_hash: None,
+        max_level: 1,
+        enable_random_level: false,
+        level_probability: None,
+        level_supply_caps: None,
+        reveal_at: None,
+        denominations: None,
+    }
+}
+
+fn sample_media_data() -> NFTMediaData {
+    NFTMediaData {
+        level_medias: vec!["level1".to_string()],
+        level_descriptors: None,
+        placeholder_media: None,
+    }
+}
+
+/// A contract owned by `accounts(0)` holding a supply of 100 NFTs as FT.
+fn new_contract(metadata: NFTContractMetadata, mediadata: NFTMediaData) -> Contract {
+    Contract::new(accounts(0), metadata, mediadata, U128(100 * UNIT))
+}
+
+/// Two random levels where every roll lands on level 2.
+fn two_level_metadata() -> NFTContractMetadata {
+    NFTContractMetadata {
+        max_level: 2,
+        enable_random_level: true,
+        level_probability: Some(vec![0, 10000]),
+        ..sample_contract_metadata()
+    }
+}
+
+fn two_level_media_data() -> NFTMediaData {
+    NFTMediaData {
+        level_medias: vec!["level1".to_string(), "level2".to_string()],
+        ..sample_media_data()
+    }
+}
+
+/// Register `account_id` and send it `units` NFTs worth of FT from the owner, minted with pending levels.
+fn mint_pending_to(contract: &mut Contract, context: &mut VMContextBuilder, account_id: AccountId, units: u128) {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    testing_env!(context
+        .predecessor_account_id(account_id.clone())
+        .attached_deposit(10u128.pow(24))
+        .build());
+    contract.storage_deposit(None, None);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.ft_transfer(account_id, U128(units * UNIT), None);
+}
+
 fn sample_token_metadata() -> TokenMetadata {
     TokenMetadata {
+        level: DEFAULT_LEVEL,
         title: Some("Olympus Mons".into()),
         description: Some("The tallest mountain in the charted solar system".into()),
         media: None,
@@ -33,22 +99,15 @@ fn sample_token_metadata() -> TokenMetadata {
         extra: None,
         reference: None,
         reference_hash: None,
+        denomination: None,
     }
 }
 
-#[test]
-#[should_panic(expected = "The contract is not initialized")]
-fn test_default() {
-    let context = get_context(accounts(1));
-    testing_env!(context.build());
-    let _contract = Contract::default();
-}
-
 #[test]
 fn test_new_account_contract() {
     let mut context = get_context(accounts(1));
     testing_env!(context.build());
-    let contract = Contract::new_default_meta(accounts(1).into());
+    let contract = new_contract(sample_contract_metadata(), sample_media_data());
     testing_env!(context.is_view(true).build());
     let contract_nft_tokens = contract.nft_tokens(Some(U128(0)), None);
     assert_eq!(contract_nft_tokens.len(), 0);
@@ -58,7 +117,7 @@ fn test_new_account_contract() {
 fn test_mint_nft() {
     let mut context = get_context(accounts(0));
     testing_env!(context.build());
-    let mut contract = Contract::new_default_meta(accounts(0).into());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
     testing_env!(context
         .storage_usage(env::storage_usage())
         .attached_deposit(MINT_STORAGE_COST)
@@ -66,7 +125,7 @@ fn test_mint_nft() {
         .build());
     let token_metadata: TokenMetadata = sample_token_metadata();
     let token_id = "0".to_string();
-    contract.nft_mint(token_id.clone(), token_metadata, accounts(0), None);
+    contract.nft_wrap_by_operator(token_metadata, accounts(0), None);
     let contract_nft_tokens = contract.nft_tokens(Some(U128(0)), None);
     assert_eq!(contract_nft_tokens.len(), 1);
 
@@ -82,7 +141,7 @@ fn test_mint_nft() {
     );
     assert_eq!(
         contract_nft_tokens[0].metadata.media,
-        sample_token_metadata().media
+        Some("level1".to_string())
     );
     assert_eq!(contract_nft_tokens[0].approved_account_ids, HashMap::new());
 }
@@ -91,7 +150,7 @@ fn test_mint_nft() {
 fn test_internal_transfer() {
     let mut context = get_context(accounts(0));
     testing_env!(context.build());
-    let mut contract = Contract::new_default_meta(accounts(0).into());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
 
     testing_env!(context
         .storage_usage(env::storage_usage())
@@ -99,7 +158,14 @@ fn test_internal_transfer() {
         .predecessor_account_id(accounts(0))
         .build());
     let token_id = "0".to_string();
-    contract.nft_mint(token_id.clone(), sample_token_metadata(), accounts(0), None);
+    contract.nft_wrap_by_operator(sample_token_metadata(), accounts(0), None);
+
+    // the receiver pays for the storage of the token it gets
+    testing_env!(context
+        .predecessor_account_id(accounts(1))
+        .attached_deposit(MINT_STORAGE_COST)
+        .build());
+    contract.storage_deposit(None, None);
 
     testing_env!(context
         .storage_usage(env::storage_usage())
@@ -135,7 +201,7 @@ fn test_internal_transfer() {
         token.metadata.description,
         sample_token_metadata().description
     );
-    assert_eq!(token.metadata.media, sample_token_metadata().media);
+    assert_eq!(token.metadata.media, Some("level1".to_string()));
     assert_eq!(token.approved_account_ids, HashMap::new());
 }
 
@@ -143,7 +209,7 @@ fn test_internal_transfer() {
 fn test_nft_approve() {
     let mut context = get_context(accounts(0));
     testing_env!(context.build());
-    let mut contract = Contract::new_default_meta(accounts(0).into());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
 
     testing_env!(context
         .storage_usage(env::storage_usage())
@@ -151,7 +217,7 @@ fn test_nft_approve() {
         .predecessor_account_id(accounts(0))
         .build());
     let token_id = "0".to_string();
-    contract.nft_mint(token_id.clone(), sample_token_metadata(), accounts(0), None);
+    contract.nft_wrap_by_operator(sample_token_metadata(), accounts(0), None);
 
     testing_env!(context
         .storage_usage(env::storage_usage())
@@ -173,7 +239,7 @@ fn test_nft_approve() {
 fn test_nft_revoke() {
     let mut context = get_context(accounts(0));
     testing_env!(context.build());
-    let mut contract = Contract::new_default_meta(accounts(0).into());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
 
     testing_env!(context
         .storage_usage(env::storage_usage())
@@ -181,7 +247,7 @@ fn test_nft_revoke() {
         .predecessor_account_id(accounts(0))
         .build());
     let token_id = "0".to_string();
-    contract.nft_mint(token_id.clone(), sample_token_metadata(), accounts(0), None);
+    contract.nft_wrap_by_operator(sample_token_metadata(), accounts(0), None);
 
     // alice approves bob
     testing_env!(context
@@ -211,7 +277,7 @@ fn test_nft_revoke() {
 fn test_revoke_all() {
     let mut context = get_context(accounts(0));
     testing_env!(context.build());
-    let mut contract = Contract::new_default_meta(accounts(0).into());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
 
     testing_env!(context
         .storage_usage(env::storage_usage())
@@ -219,7 +285,7 @@ fn test_revoke_all() {
         .predecessor_account_id(accounts(0))
         .build());
     let token_id = "0".to_string();
-    contract.nft_mint(token_id.clone(), sample_token_metadata(), accounts(0), None);
+    contract.nft_wrap_by_operator(sample_token_metadata(), accounts(0), None);
 
     // alice approves bob
     testing_env!(context
@@ -249,7 +315,7 @@ fn test_revoke_all() {
 fn test_internal_remove_token_from_owner() {
     let mut context = get_context(accounts(0));
     testing_env!(context.build());
-    let mut contract = Contract::new_default_meta(accounts(0).into());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
 
     testing_env!(context
         .storage_usage(env::storage_usage())
@@ -257,12 +323,12 @@ fn test_internal_remove_token_from_owner() {
         .predecessor_account_id(accounts(0))
         .build());
     let token_id = "0".to_string();
-    contract.nft_mint(token_id.clone(), sample_token_metadata(), accounts(0), None);
+    contract.nft_wrap_by_operator(sample_token_metadata(), accounts(0), None);
 
     let contract_nft_tokens_before = contract.nft_tokens_for_owner(accounts(0), None, None);
     assert_eq!(contract_nft_tokens_before.len(), 1);
 
-    contract.internal_remove_token_from_owner(&accounts(0), &token_id);
+    contract.internal_remove_token_from_owner(&accounts(0), &token_id, &DEFAULT_LEVEL);
     let contract_nft_tokens_after = contract.nft_tokens_for_owner(accounts(0), None, None);
     assert_eq!(contract_nft_tokens_after.len(), 0);
 }
@@ -272,7 +338,7 @@ fn test_nft_payout() {
     use crate::royalty::NonFungibleTokenCore;
     let mut context = get_context(accounts(0));
     testing_env!(context.build());
-    let mut contract = Contract::new_default_meta(accounts(0).into());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
 
     testing_env!(context
         .storage_usage(env::storage_usage())
@@ -280,7 +346,7 @@ fn test_nft_payout() {
         .predecessor_account_id(accounts(0))
         .build());
     let token_id = "0".to_string();
-    contract.nft_mint(token_id.clone(), sample_token_metadata(), accounts(0), None);
+    contract.nft_wrap_by_operator(sample_token_metadata(), accounts(0), None);
 
     // alice approves bob
     testing_env!(context
@@ -299,16 +365,1428 @@ fn test_nft_payout() {
 fn test_nft_total_supply() {
     let mut context = get_context(accounts(0));
     testing_env!(context.build());
-    let mut contract = Contract::new_default_meta(accounts(0).into());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
 
     testing_env!(context
         .storage_usage(env::storage_usage())
         .attached_deposit(MINT_STORAGE_COST)
         .predecessor_account_id(accounts(0))
         .build());
-    let token_id = "0".to_string();
-    contract.nft_mint(token_id.clone(), sample_token_metadata(), accounts(0), None);
+    contract.nft_wrap_by_operator(sample_token_metadata(), accounts(0), None);
 
     let total_supply = contract.nft_total_supply();
     assert_eq!(total_supply, U128(1));
-}
\ No newline at end of file
+}
+
+/// Replays the nep141 and nep171 logs into per-account balances. FT moves with ft_mint, ft_burn and
+/// ft_transfer, and every NFT an account holds counts as `units_per_nft` times its denomination, known
+/// from the nrc404 note_mint event, so the replay must match `ft_balance_of` which adds the two together.
+fn replay_balances(logs: &[String], unit: u128) -> HashMap<String, i128> {
+    let mut balances: HashMap<String, i128> = HashMap::new();
+    let mut denominations: HashMap<String, u128> = HashMap::new();
+    for log in logs.iter() {
+        let event: near_sdk::serde_json::Value = match log.strip_prefix("EVENT_JSON:") {
+            Some(json) => near_sdk::serde_json::from_str(json).unwrap(),
+            None => continue,
+        };
+        let standard = event["standard"].as_str().unwrap();
+        for data in event["data"].as_array().unwrap() {
+            let amount = match standard {
+                "nep141" => data["amount"].as_str().unwrap().parse::<u128>().unwrap(),
+                "nep171" => data["token_ids"].as_array().unwrap().iter()
+                    .map(|token_id| denominations.get(token_id.as_str().unwrap()).copied().unwrap_or(1) * unit)
+                    .sum(),
+                "nrc404" => {
+                    if event["event"] == "note_mint" {
+                        denominations.insert(data["token_id"].as_str().unwrap().to_string(), data["denomination"].as_u64().unwrap() as u128);
+                    }
+                    continue;
+                }
+                _ => continue,
+            } as i128;
+            match event["event"].as_str().unwrap() {
+                "ft_mint" | "nft_mint" => {
+                    *balances.entry(data["owner_id"].as_str().unwrap().to_string()).or_default() += amount;
+                }
+                "ft_burn" | "nft_burn" => {
+                    *balances.entry(data["owner_id"].as_str().unwrap().to_string()).or_default() -= amount;
+                }
+                "ft_transfer" | "nft_transfer" => {
+                    *balances.entry(data["old_owner_id"].as_str().unwrap().to_string()).or_default() -= amount;
+                    *balances.entry(data["new_owner_id"].as_str().unwrap().to_string()).or_default() += amount;
+                }
+                _ => {}
+            }
+        }
+    }
+    balances
+}
+
+#[test]
+fn test_event_replay_matches_ft_balance() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::nft_core::NonFungibleTokenCore as _;
+    use near_sdk::test_utils::get_logs;
+
+    let unit = UNIT;
+    let mut logs = vec![];
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    logs.extend(get_logs());
+
+    // register the holders with enough storage balance for their NFTs
+    for account_id in [accounts(1), accounts(2)] {
+        testing_env!(context
+            .predecessor_account_id(account_id.clone())
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.storage_deposit(None, None);
+        logs.extend(get_logs());
+    }
+
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.set_protocol_fee_rate(U128(20000));
+    contract.ft_transfer(accounts(1), U128(550), None);
+    logs.extend(get_logs());
+
+    // covering the transfer unwraps some of bob's NFTs, charlie gets new ones minted
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.ft_transfer(accounts(2), U128(260), None);
+    logs.extend(get_logs());
+
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nft_transfer(accounts(2), token_id, None, None);
+    logs.extend(get_logs());
+
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_set_skip_nft(true);
+    logs.extend(get_logs());
+
+    // unwrapped units stay fungible, only the newly received NFT is minted
+    let charlie_nfts = contract.nft_supply_for_owner(accounts(2)).0;
+    let token_id = contract.nft_tokens_for_owner(accounts(2), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+    contract.nft_unwrap(vec![token_id]);
+    logs.extend(get_logs());
+    assert_eq!(contract.nft_supply_for_owner(accounts(2)).0, charlie_nfts - 1);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.ft_transfer(accounts(2), U128(unit), None);
+    logs.extend(get_logs());
+    assert_eq!(contract.nft_supply_for_owner(accounts(2)).0, charlie_nfts);
+
+    let balances = replay_balances(&logs, unit);
+    for account_id in [accounts(0), accounts(1), accounts(2), env::current_account_id()] {
+        assert_eq!(
+            balances.get(account_id.as_str()).copied().unwrap_or(0),
+            contract.ft_balance_of(account_id.clone()).0 as i128,
+            "replayed balance of {}",
+            account_id
+        );
+    }
+    assert!(contract.nft_supply_for_owner(accounts(2)).0 > 0);
+}
+
+#[test]
+fn test_denomination_notes() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::nft_core::NonFungibleTokenCore as _;
+    use near_sdk::test_utils::get_logs;
+
+    let unit = UNIT;
+    let mut logs = vec![];
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let metadata = NFTContractMetadata { denominations: Some(vec![1, 10]), ..sample_contract_metadata() };
+    let mut contract = new_contract(metadata, sample_media_data());
+    logs.extend(get_logs());
+    for account_id in [accounts(1), accounts(2)] {
+        testing_env!(context
+            .predecessor_account_id(account_id.clone())
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.storage_deposit(None, None);
+        logs.extend(get_logs());
+    }
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.set_protocol_fee_rate(U128(0));
+
+    // 25 units are materialized as two notes of 10 and five single notes
+    contract.ft_transfer(accounts(1), U128(25 * unit), None);
+    logs.extend(get_logs());
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 7);
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, 25 * unit);
+    let tokens = contract.nft_tokens_for_owner(accounts(1), None, None);
+    assert_eq!(tokens.iter().filter(|token| token.metadata.denomination == Some(10)).count(), 2);
+
+    // paying 7 units spends the single notes and breaks a note of 10, the change comes back as single notes
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.ft_transfer(accounts(2), U128(7 * unit), None);
+    logs.extend(get_logs());
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, 18 * unit);
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 9);
+    assert_eq!(contract.ft_balance_of(accounts(2)).0, 7 * unit);
+    assert_eq!(contract.nft_supply_for_owner(accounts(2)).0, 7);
+
+    // moving a note moves all of its units
+    let note_id = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter()
+        .find(|token| token.metadata.denomination == Some(10)).unwrap().token_id;
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nft_transfer(accounts(2), note_id.clone(), None, None);
+    logs.extend(get_logs());
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, 8 * unit);
+    assert_eq!(contract.ft_balance_of(accounts(2)).0, 17 * unit);
+
+    // unwrapping the note credits all of its units
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+    contract.nft_unwrap(vec![note_id]);
+    logs.extend(get_logs());
+    assert_eq!(contract.nrc404_kept_ft_units(accounts(2)).0, 10);
+
+    let balances = replay_balances(&logs, unit);
+    for account_id in [accounts(0), accounts(1), accounts(2), env::current_account_id()] {
+        assert_eq!(
+            balances.get(account_id.as_str()).copied().unwrap_or(0),
+            contract.ft_balance_of(account_id.clone()).0 as i128,
+            "replayed balance of {}",
+            account_id
+        );
+    }
+}
+
+#[test]
+fn test_level_supply_cap_falls_back() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let metadata = NFTContractMetadata { level_supply_caps: Some(vec![None, Some(2)]), ..two_level_metadata() };
+    let mut contract = new_contract(metadata, two_level_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 5);
+
+    testing_env!(context.block_index(LEVEL_REVEAL_DELAY_BLOCKS).build());
+    assert_eq!(contract.nrc404_reveal(accounts(1), None), 5);
+    let capacity = contract.nrc404_level_capacity();
+    assert_eq!(capacity[0].supply.0, 3);
+    assert_eq!(capacity[0].remaining, None);
+    assert_eq!(capacity[1].supply.0, 2);
+    assert_eq!(capacity[1].remaining, Some(U128(0)));
+}
+
+#[test]
+fn test_level_descriptors() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mediadata = NFTMediaData {
+        level_descriptors: Some(vec![
+            LevelDescriptor { title: None, description: None, media: None, media_hash: None, extra: None, reference: None, reference_hash: None },
+            LevelDescriptor {
+                title: Some("Epic #{id}".to_string()),
+                description: Some("An epic token".to_string()),
+                media: Some("epic".to_string()),
+                media_hash: None,
+                extra: Some("{\"rarity\":\"epic\"}".to_string()),
+                reference: None,
+                reference_hash: None,
+            },
+        ]),
+        ..two_level_media_data()
+    };
+    let metadata = NFTContractMetadata { level_probability: Some(vec![5000, 5000]), ..two_level_metadata() };
+    let mut contract = new_contract(metadata, mediadata);
+    mint_pending_to(&mut contract, &mut context, accounts(1), 6);
+    testing_env!(context.block_index(LEVEL_REVEAL_DELAY_BLOCKS).build());
+    contract.nrc404_reveal(accounts(1), None);
+
+    // the level descriptor is rendered into the token metadata
+    let tokens = contract.nft_tokens_for_owner(accounts(1), None, None);
+    assert!(tokens.iter().any(|token| token.metadata.level == 1) && tokens.iter().any(|token| token.metadata.level == 2));
+    for token in tokens {
+        if token.metadata.level == 2 {
+            assert_eq!(token.metadata.title, Some(format!("Epic #{}", token.token_id)));
+            assert_eq!(token.metadata.description, Some("An epic token".to_string()));
+            assert_eq!(token.metadata.media, Some("epic".to_string()));
+            assert_eq!(token.metadata.extra, Some("{\"rarity\":\"epic\"}".to_string()));
+        } else {
+            assert_eq!(token.metadata.level, 1);
+            assert_eq!(token.metadata.title, None);
+            assert_eq!(token.metadata.media, Some("level1".to_string()));
+        }
+    }
+}
+
+fn placeholder_media_data() -> NFTMediaData {
+    NFTMediaData { placeholder_media: Some("placeholder".to_string()), ..two_level_media_data() }
+}
+
+#[test]
+fn test_placeholder_until_reveal() {
+    use near_sdk::test_utils::get_logs;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(two_level_metadata(), placeholder_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 3);
+    testing_env!(context.block_index(LEVEL_REVEAL_DELAY_BLOCKS).build());
+    assert_eq!(contract.nrc404_reveal(accounts(1), None), 3);
+
+    // neither the level nor the seed it can be recomputed from are logged before the reveal
+    let logs = get_logs();
+    assert!(logs.iter().any(|log| log.contains("\"level_assigned\"")));
+    assert!(logs.iter().all(|log| !log.contains("\"level\"") && !log.contains("\"random_seed\"")));
+    assert!(!contract.nrc404_is_revealed());
+    for token in contract.nft_tokens_for_owner(accounts(1), None, None) {
+        assert_eq!(token.metadata.level, PENDING_LEVEL);
+        assert_eq!(token.metadata.media, Some("placeholder".to_string()));
+    }
+    assert!(contract.nrc404_holders(None, None).iter().all(|holder| holder.level_counts.is_empty()));
+
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_reveal_collection();
+    assert!(contract.nrc404_is_revealed());
+    assert!(get_logs().is_empty());
+
+    // the token IDs are announced page by page
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_announce_reveal(Some(U128(1)), Some(1));
+    assert_eq!(get_logs(), vec!["EVENT_JSON:{\"standard\":\"nep171\",\"version\":\"1.1.0\",\"event\":\"nft_metadata_update\",\"data\":[{\"token_ids\":[\"1\"],\"memo\":\"reveal\"}]}"]);
+    for token in contract.nft_tokens_for_owner(accounts(1), None, None) {
+        assert_eq!(token.metadata.level, 2);
+        assert_eq!(token.metadata.media, Some("level2".to_string()));
+    }
+    assert_eq!(contract.nrc404_supply_for_owner_by_level(accounts(1), 2).0, 3);
+}
+
+#[test]
+#[should_panic(expected = "levels are hidden until the collection is revealed")]
+fn test_level_views_hidden_until_reveal() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(two_level_metadata(), placeholder_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 1);
+    contract.nrc404_level_distribution();
+}
+
+#[test]
+fn test_backfill_migrated_indexes() {
+    use crate::nft_core::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 3);
+    let storage_used = contract.storage_used.get(&accounts(1)).unwrap();
+
+    // the state migrated from the first release only keeps the tokens
+    contract.level_supply.remove(&DEFAULT_LEVEL);
+    contract.holders.clear();
+    contract.storage_used.remove(&accounts(1));
+    contract.index_backfill_cursor = Some(0);
+
+    testing_env!(context.attached_deposit(1).build());
+    assert!(!contract.nrc404_backfill_indexes(Some(2)));
+    assert!(contract.nrc404_backfill_indexes(Some(2)));
+    assert_eq!(contract.nrc404_supply_by_level(DEFAULT_LEVEL).0, 3);
+    assert_eq!(contract.nrc404_holder_count().0, 1);
+    assert_eq!(contract.storage_used.get(&accounts(1)), Some(storage_used));
+
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_backfill_holders(vec![accounts(0), accounts(2)]);
+    assert_eq!(contract.nrc404_holder_count().0, 2);
+
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(Some(accounts(2)), None);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nft_transfer(accounts(2), token_id, None, None);
+    assert_eq!(contract.nrc404_supply_by_level(DEFAULT_LEVEL).0, 3);
+}
+
+#[test]
+fn test_transfer_to_migrated_holder() {
+    use crate::nft_core::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 1);
+    mint_pending_to(&mut contract, &mut context, accounts(2), 1);
+    testing_env!(context.predecessor_account_id(accounts(3)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(None, None);
+
+    // the first release kept no storage balances
+    contract.level_supply.remove(&DEFAULT_LEVEL);
+    contract.holders.clear();
+    for account_id in [accounts(1), accounts(2), accounts(3)] {
+        contract.storage_used.remove(&account_id);
+        contract.storage_deposits.remove(&account_id);
+    }
+    contract.index_backfill_cursor = Some(0);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    assert!(contract.nrc404_backfill_indexes(None));
+    contract.nrc404_backfill_holders(vec![accounts(3)]);
+
+    let min = contract.storage_balance_bounds().min.0;
+    let cost_per_nft = contract.nrc404_storage_cost_per_nft().0;
+    let storage_balance = contract.storage_balance_of(accounts(1)).unwrap();
+    assert_eq!(storage_balance.total.0, min + cost_per_nft);
+    assert_eq!(storage_balance.available.0, 0);
+    let storage_balance = contract.storage_balance_of(accounts(3)).unwrap();
+    assert_eq!(storage_balance.total.0, min);
+    assert_eq!(storage_balance.available.0, 0);
+
+    // a migrated holder pays for a new token like anyone else
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(cost_per_nft).build());
+    contract.storage_deposit(None, None);
+    let token_id = contract.nft_tokens_for_owner(accounts(2), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+    contract.nft_transfer(accounts(1), token_id, None, None);
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 2);
+    assert_eq!(contract.storage_balance_of(accounts(1)).unwrap().available.0, 0);
+}
+
+#[test]
+#[should_panic(expected = "token indexes are still being backfilled after the migration")]
+fn test_nft_transfer_waits_for_backfill() {
+    use crate::nft_core::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 1);
+    contract.index_backfill_cursor = Some(0);
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nft_transfer(accounts(0), token_id, None, None);
+}
+
+#[test]
+fn test_locked_levels_deduped() {
+    let mut context = get_context(accounts(1));
+    testing_env!(context.build());
+    let mut contract = new_contract(two_level_metadata(), two_level_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_set_locked_levels(vec![2, 1, 2, 1, 2]);
+    assert_eq!(contract.nrc404_locked_levels(accounts(1)), vec![1, 2]);
+}
+
+#[test]
+#[should_panic(expected = "NFTs can only be sent to the contract with nft_transfer_call")]
+fn test_nft_transfer_to_contract() {
+    use crate::nft_core::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 1);
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nft_transfer(env::current_account_id(), token_id, None, None);
+}
+
+#[test]
+fn test_skip_nft_burns_locked_tokens() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 2);
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_lock_tokens(vec![token_id.clone()], true);
+    contract.nrc404_set_locked_levels(vec![DEFAULT_LEVEL]);
+
+    contract.nrc404_set_skip_nft(true);
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 0);
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, 2 * UNIT);
+    assert!(!contract.nrc404_is_token_locked(token_id));
+    assert!(contract.nrc404_locked_levels(accounts(1)).is_empty());
+}
+
+#[test]
+fn test_process_pending_skips_white_listed() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_pause(vec![PauseSwitch::Wrap]);
+    mint_pending_to(&mut contract, &mut context, accounts(1), 2);
+    assert_eq!(contract.nrc404_pending_mints_of(accounts(1)).0, 2);
+
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.set_mint_white(accounts(1), true);
+    contract.nrc404_unpause(vec![PauseSwitch::Wrap]);
+    assert_eq!(contract.nrc404_process_pending(accounts(1), None).0, 0);
+    assert_eq!(contract.nrc404_pending_mints_of(accounts(1)).0, 0);
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 0);
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, 2 * UNIT);
+}
+
+#[test]
+fn test_fusion_surplus_stays_fungible() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let metadata = NFTContractMetadata { level_probability: Some(vec![10000, 0]), ..two_level_metadata() };
+    let mut contract = new_contract(metadata, two_level_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 3);
+    testing_env!(context.block_index(LEVEL_REVEAL_DELAY_BLOCKS).attached_deposit(1).build());
+    contract.nrc404_reveal(accounts(1), None);
+    contract.set_fusion_cost(DEFAULT_LEVEL, 3);
+
+    let token_ids = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+    testing_env!(context.predecessor_account_id(accounts(1)).block_index(LEVEL_REVEAL_DELAY_BLOCKS + 1).attached_deposit(1).build());
+    contract.nrc404_fuse(token_ids);
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 1);
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, 3 * UNIT);
+
+    // the next balance change doesn't wrap the surplus back into NFTs
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.ft_transfer(accounts(1), U128(1), None);
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 1);
+}
+
+#[test]
+#[should_panic(expected = "insufficient storage balance, call storage_deposit first")]
+fn test_nft_transfer_needs_receiver_storage() {
+    use crate::nft_core::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 1);
+
+    // the receiver only pays for its registration
+    let min_storage = contract.storage_balance_bounds().min.0;
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(min_storage).build());
+    contract.storage_deposit(None, Some(true));
+
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nft_transfer(accounts(2), token_id, None, None);
+}
+
+#[test]
+fn test_nft_resolve_transfer_moves_storage_back() {
+    use crate::nft_core::{NonFungibleTokenCore as _, NonFungibleTokenResolver};
+    use near_sdk::{PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 1);
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(None, None);
+
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    let _ = contract.nft_transfer_call(accounts(2), token_id.clone(), None, None, "".to_string());
+    assert_eq!(contract.storage_used.get(&accounts(1)), None);
+    assert_eq!(contract.storage_used.get(&accounts(2)), Some(contract.bytes_per_nft));
+
+    // the receiver asks for the token to be returned
+    testing_env!(
+        context.predecessor_account_id(env::current_account_id()).attached_deposit(0).build(),
+        VMConfig::test(),
+        RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![PromiseResult::Successful(b"true".to_vec())],
+    );
+    assert!(!contract.nft_resolve_transfer(None, accounts(1), accounts(2), token_id, HashMap::new(), None));
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 1);
+    assert_eq!(contract.storage_used.get(&accounts(1)), Some(contract.bytes_per_nft));
+    assert_eq!(contract.storage_used.get(&accounts(2)), None);
+}
+
+#[test]
+#[should_panic(expected = "The amount is greater than the available storage balance")]
+fn test_storage_withdraw_keeps_nft_storage() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 1);
+
+    // everything above the registration but the storage of the NFT is still available
+    let storage_balance = contract.storage_balance_of(accounts(1)).unwrap();
+    let nft_storage = u128::from(contract.bytes_per_nft) * env::storage_byte_cost();
+    assert_eq!(storage_balance.available.0, 10u128.pow(24) - contract.storage_balance_bounds().min.0 - nft_storage);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.storage_withdraw(Some(U128(storage_balance.available.0 + 1)));
+}
+
+#[test]
+fn test_level_lock_ignores_level_revealed_in_block() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use near_sdk::test_utils::get_logs;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(two_level_metadata(), two_level_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 1);
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(None, None);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_set_locked_levels(vec![1, 2]);
+
+    // the transfer reveals the token and has to burn it, the lock can't turn the roll into a revert
+    testing_env!(context.block_index(LEVEL_REVEAL_DELAY_BLOCKS).build());
+    contract.ft_transfer(accounts(2), U128(UNIT), None);
+    assert!(get_logs().iter().any(|log| log.contains("\"level_assigned\"")));
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 0);
+    assert_eq!(contract.nft_tokens_for_owner(accounts(2), None, None)[0].metadata.level, 2);
+}
+
+#[test]
+#[should_panic(expected = "level was revealed in this block, try again in the next one")]
+fn test_fuse_waits_for_revealed_level() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(two_level_metadata(), two_level_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 2);
+    testing_env!(context.attached_deposit(1).build());
+    contract.set_fusion_cost(1, 2);
+    testing_env!(context.predecessor_account_id(accounts(1)).block_index(LEVEL_REVEAL_DELAY_BLOCKS).build());
+    contract.nrc404_reveal(accounts(1), None);
+    let token_ids = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+    contract.nrc404_fuse(token_ids);
+}
+
+/// A collection hiding its levels, with `units` tokens of accounts(1) revealed to level 2 in the previous block.
+fn hidden_level_contract(context: &mut VMContextBuilder, units: u128) -> Contract {
+    testing_env!(context.build());
+    let mut contract = new_contract(two_level_metadata(), placeholder_media_data());
+    mint_pending_to(&mut contract, context, accounts(1), units);
+    testing_env!(context.block_index(LEVEL_REVEAL_DELAY_BLOCKS).build());
+    contract.nrc404_reveal(accounts(1), None);
+    testing_env!(context.block_index(LEVEL_REVEAL_DELAY_BLOCKS + 1).build());
+    contract
+}
+
+#[test]
+fn test_hidden_levels_pay_default_royalty() {
+    use crate::royalty::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = hidden_level_contract(&mut context, 1);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.set_level_royalty_rate(2, Some(1000));
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+
+    assert_eq!(contract.get_level_royalty_rate(2), None);
+    assert_eq!(contract.get_default_royalty(Some(2)), contract.get_default_royalty(None));
+    let payout = contract.nft_payout(token_id.clone(), U128(10000), 10).payout;
+    assert_eq!(payout, HashMap::from([(accounts(1), U128(10000))]));
+
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_reveal_collection();
+    assert_eq!(contract.get_level_royalty_rate(2), Some(1000));
+    let payout = contract.nft_payout(token_id, U128(10000), 10).payout;
+    assert_eq!(payout, HashMap::from([(accounts(1), U128(9000)), (accounts(0), U128(1000))]));
+}
+
+#[test]
+fn test_hidden_levels_dont_steer_burns() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = hidden_level_contract(&mut context, 2);
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(None, None);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_set_locked_levels(vec![2]);
+    let mut token_ids: Vec<String> = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+    token_ids.sort_by_key(|token_id| token_id.parse::<u128>().unwrap());
+
+    // the locked level doesn't hold the token back and the oldest one goes first
+    contract.ft_transfer(accounts(2), U128(UNIT), None);
+    let remaining: Vec<String> = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+    assert_eq!(remaining, vec![token_ids[1].clone()]);
+}
+
+#[test]
+#[should_panic(expected = "levels are hidden until the collection is revealed")]
+fn test_fuse_hidden_levels() {
+    let mut context = get_context(accounts(0));
+    let mut contract = hidden_level_contract(&mut context, 2);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.set_fusion_cost(1, 2);
+    let token_ids = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_fuse(token_ids);
+}
+
+#[test]
+#[should_panic(expected = "levels are hidden until the collection is revealed")]
+fn test_reroll_hidden_levels() {
+    use crate::RerollConfig;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = hidden_level_contract(&mut context, 1);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.set_reroll_config(Some(RerollConfig { price: U128(0), burn: false, cooldown_ms: 0 }));
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_reroll(token_id);
+}
+
+#[test]
+fn test_burn_order_with_notes() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::{BurnOrder, NOTE_BREAK_MEMO};
+    use near_sdk::test_utils::get_logs;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let metadata = NFTContractMetadata { denominations: Some(vec![1, 10]), ..sample_contract_metadata() };
+    let mut contract = new_contract(metadata, sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.set_protocol_fee_rate(U128(0));
+    // two notes of 10 and then five single notes
+    mint_pending_to(&mut contract, &mut context, accounts(1), 25);
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(None, None);
+    let singles: Vec<String> = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter()
+        .filter(|token| token.metadata.denomination == Some(1))
+        .map(|token| token.token_id)
+        .collect();
+    assert_eq!(singles.len(), 5);
+
+    // the newest tokens are the singles, they go before a note that would fit
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_set_burn_order(BurnOrder::NewestFirst);
+    testing_env!(context.build());
+    contract.ft_transfer(accounts(2), U128(10 * UNIT), None);
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, 15 * UNIT);
+    let logs = get_logs();
+    assert!(singles.iter().all(|token_id| logs.iter().any(|log| log.contains("\"nft_unwrap\"") && log.contains(&format!("\"{}\"", token_id)))));
+    // the rest is taken out of a note, the change stays with the holder
+    assert!(logs.iter().any(|log| log.contains(NOTE_BREAK_MEMO)));
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 6);
+}
+
+#[test]
+fn test_burn_order_follows_receive_order() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::BurnOrder;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.set_protocol_fee_rate(U128(0));
+    mint_pending_to(&mut contract, &mut context, accounts(1), 2);
+    mint_pending_to(&mut contract, &mut context, accounts(2), 2);
+
+    // token 0 goes through the reserve and is minted again for accounts(2), after its tokens 2 and 3
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.ft_transfer(accounts(2), U128(UNIT), None);
+    let token_ids = |contract: &Contract| {
+        let mut token_ids: Vec<String> = contract.nft_tokens_for_owner(accounts(2), None, None).into_iter().map(|token| token.token_id).collect();
+        token_ids.sort();
+        token_ids
+    };
+    assert_eq!(token_ids(&contract), vec!["0", "2", "3"]);
+
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+    contract.nrc404_set_burn_order(BurnOrder::NewestFirst);
+    contract.ft_transfer(accounts(1), U128(UNIT), None);
+    assert_eq!(token_ids(&contract), vec!["2", "3"]);
+    contract.nrc404_set_burn_order(BurnOrder::OldestFirst);
+    contract.ft_transfer(accounts(1), U128(UNIT), None);
+    assert_eq!(token_ids(&contract), vec!["3"]);
+}
+
+#[test]
+fn test_unregister_clears_account_settings() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(None, None);
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_set_referrer(accounts(2));
+    contract.nrc404_set_royalty_recipient(Some(accounts(3)));
+    assert!(contract.storage_unregister(None));
+    assert_eq!(contract.nrc404_referrer_of(accounts(1)), None);
+    assert_eq!(contract.nrc404_royalty_recipient_of(accounts(1)), accounts(1));
+}
+
+#[test]
+#[should_panic(expected = "claim the fees in the vault before unregistering")]
+fn test_unregister_with_unclaimed_fees() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(None, None);
+    contract.fee_vault.insert(&accounts(1), &UNIT);
+    testing_env!(context.attached_deposit(1).build());
+    contract.storage_unregister(Some(true));
+}
+
+#[test]
+#[should_panic(expected = "renounce the roles before unregistering")]
+fn test_unregister_with_roles() {
+    use crate::Role;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.grant_role(accounts(1), Role::Pauser);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(None, None);
+    testing_env!(context.attached_deposit(1).build());
+    contract.storage_unregister(Some(true));
+}
+
+#[test]
+#[should_panic(expected = "wrapping is paused")]
+fn test_fuse_while_wrap_paused() {
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let metadata = NFTContractMetadata { level_probability: Some(vec![10000, 0]), ..two_level_metadata() };
+    let mut contract = new_contract(metadata, two_level_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 2);
+    testing_env!(context.block_index(LEVEL_REVEAL_DELAY_BLOCKS).attached_deposit(1).build());
+    contract.nrc404_reveal(accounts(1), None);
+    contract.set_fusion_cost(DEFAULT_LEVEL, 2);
+    contract.nrc404_pause(vec![PauseSwitch::Wrap]);
+
+    let token_ids = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+    testing_env!(context.predecessor_account_id(accounts(1)).block_index(LEVEL_REVEAL_DELAY_BLOCKS + 1).attached_deposit(1).build());
+    contract.nrc404_fuse(token_ids);
+}
+
+#[test]
+#[should_panic(expected = "level supply cap reached")]
+fn test_fuse_into_full_level() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let metadata = NFTContractMetadata {
+        level_probability: Some(vec![10000, 0]),
+        level_supply_caps: Some(vec![None, Some(1)]),
+        ..two_level_metadata()
+    };
+    let mut contract = new_contract(metadata, two_level_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 4);
+    testing_env!(context.block_index(LEVEL_REVEAL_DELAY_BLOCKS).attached_deposit(1).build());
+    contract.nrc404_reveal(accounts(1), None);
+    contract.set_fusion_cost(DEFAULT_LEVEL, 2);
+
+    let token_ids: Vec<String> = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+    testing_env!(context.predecessor_account_id(accounts(1)).block_index(LEVEL_REVEAL_DELAY_BLOCKS + 1).attached_deposit(1).build());
+    contract.nrc404_fuse(token_ids[..2].to_vec());
+    assert_eq!(contract.nrc404_supply_by_level(2).0, 1);
+    contract.nrc404_fuse(token_ids[2..].to_vec());
+}
+
+#[test]
+fn test_fee_split_and_claim() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::{FeeRecipient, FeeSplit};
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.set_protocol_fee_rate(U128(0));
+    mint_pending_to(&mut contract, &mut context, accounts(1), 20);
+    mint_pending_to(&mut contract, &mut context, accounts(2), 1);
+
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.set_protocol_fee_rate(U128(50000));
+    contract.set_treasury(accounts(3));
+    contract.set_fee_splits(vec![
+        FeeSplit { recipient: FeeRecipient::Treasury, share: 3333 },
+        FeeSplit { recipient: FeeRecipient::Referrer, share: 3333 },
+        FeeSplit { recipient: FeeRecipient::Account(accounts(4)), share: 3334 },
+    ]);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_set_referrer(accounts(2));
+    assert_eq!(contract.nrc404_referrer_of(accounts(1)), Some(accounts(2)));
+
+    // 5% of 1001 is 50, the shares are 16 each and the 2 left over go to the treasury
+    contract.ft_transfer(accounts(2), U128(1001), None);
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, 2000 - 1001);
+    assert_eq!(contract.ft_balance_of(accounts(2)).0, UNIT + 951);
+    assert_eq!(contract.ft_balance_of(env::current_account_id()).0, 50);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(3)).0, 18);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(2)).0, 16);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(4)).0, 16);
+
+    // the vault keeps adding up until it is claimed
+    contract.ft_transfer(accounts(2), U128(100), None);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(3)).0, 18 + 3);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(2)).0, 16 + 1);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(4)).0, 16 + 1);
+
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+    assert_eq!(contract.nrc404_claim_fees().0, 17);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(2)).0, 0);
+    assert_eq!(contract.ft_balance_of(accounts(2)).0, UNIT + 951 + 95 + 17);
+    assert_eq!(contract.ft_balance_of(env::current_account_id()).0, 55 - 17);
+}
+
+#[test]
+fn test_fees_without_referrer_go_to_treasury() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::{FeeRecipient, FeeSplit};
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.set_protocol_fee_rate(U128(0));
+    mint_pending_to(&mut contract, &mut context, accounts(1), 10);
+    mint_pending_to(&mut contract, &mut context, accounts(2), 1);
+
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.set_protocol_fee_rate(U128(50000));
+    contract.set_fee_splits(vec![
+        FeeSplit { recipient: FeeRecipient::Treasury, share: 8000 },
+        FeeSplit { recipient: FeeRecipient::Referrer, share: 2000 },
+    ]);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.ft_transfer(accounts(2), U128(400), None);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(0)).0, 20);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(2)).0, 0);
+}
+
+#[test]
+#[should_panic(expected = "no fees to claim")]
+fn test_claim_without_fees() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 1);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_claim_fees();
+}
+
+#[test]
+#[should_panic(expected = "Referrer already set")]
+fn test_referrer_set_once() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_set_referrer(accounts(2));
+    contract.nrc404_set_referrer(accounts(3));
+}
+
+#[test]
+#[should_panic(expected = "Can't refer yourself")]
+fn test_referrer_not_self() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_set_referrer(accounts(1));
+}
+
+#[test]
+fn test_two_step_ownership() {
+    use near_sdk::test_utils::get_logs;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.propose_owner(accounts(1));
+    assert!(get_logs()[0].contains("\"event\":\"ownership_proposed\""));
+    assert_eq!(contract.get_owner(), accounts(0));
+    assert_eq!(contract.get_pending_owner(), Some(accounts(1)));
+
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.accept_owner();
+    assert!(get_logs()[0].contains("\"event\":\"ownership_transferred\""));
+    assert_eq!(contract.get_owner(), accounts(1));
+    assert_eq!(contract.get_pending_owner(), None);
+}
+
+#[test]
+#[should_panic(expected = "ERR_NOT_ALLOWED")]
+fn test_accept_owner_not_proposed() {
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.propose_owner(accounts(1));
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+    contract.accept_owner();
+}
+
+#[test]
+#[should_panic(expected = "ERR_NOT_ALLOWED")]
+fn test_propose_owner_not_owner() {
+    let mut context = get_context(accounts(1));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.propose_owner(accounts(1));
+}
+
+#[test]
+fn test_grant_revoke_and_renounce_roles() {
+    use crate::Role;
+    use near_sdk::test_utils::get_logs;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.grant_role(accounts(1), Role::Admin);
+    assert!(get_logs()[0].contains("\"event\":\"role_granted\""));
+    assert!(contract.has_role(accounts(1), Role::Admin));
+
+    // an admin manages every role but admin
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.grant_role(accounts(2), Role::Pauser);
+    contract.grant_role(accounts(2), Role::FeeManager);
+    // granting a role twice doesn't add it again
+    contract.grant_role(accounts(2), Role::Pauser);
+    assert_eq!(contract.roles_of(accounts(2)), vec![Role::Pauser, Role::FeeManager]);
+    assert_eq!(contract.role_members(Role::Pauser, None, None), vec![accounts(2)]);
+    contract.revoke_role(accounts(2), Role::Pauser);
+    assert!(get_logs().last().unwrap().contains("\"event\":\"role_revoked\""));
+    assert!(!contract.has_role(accounts(2), Role::Pauser));
+    assert!(contract.has_role(accounts(2), Role::FeeManager));
+
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+    contract.renounce_role(Role::FeeManager);
+    assert!(get_logs()[0].contains("\"event\":\"role_revoked\""));
+    assert!(contract.roles_of(accounts(2)).is_empty());
+    assert!(contract.role_members(Role::FeeManager, None, None).is_empty());
+
+    // the owner holds every role without a grant
+    assert!(contract.has_role(accounts(0), Role::Upgrader));
+}
+
+#[test]
+#[should_panic(expected = "ERR_NOT_ALLOWED")]
+fn test_grant_role_not_admin() {
+    use crate::Role;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.grant_role(accounts(1), Role::Pauser);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.grant_role(accounts(2), Role::Pauser);
+}
+
+#[test]
+#[should_panic(expected = "ERR_NOT_ALLOWED")]
+fn test_admin_cant_grant_admin() {
+    use crate::Role;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.grant_role(accounts(1), Role::Admin);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.grant_role(accounts(2), Role::Admin);
+}
+
+#[test]
+#[should_panic(expected = "ERR_NOT_ALLOWED")]
+fn test_revoked_role_loses_access() {
+    use crate::Role;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.grant_role(accounts(1), Role::FeeManager);
+    contract.revoke_role(accounts(1), Role::FeeManager);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.set_protocol_fee_rate(U128(0));
+}
+
+/// accounts(1) holding 2 NFTs and 85 units of FT, with protocol fees to claim for accounts(0) and `switches` paused.
+/// The context is left on accounts(1) with 1 yoctoNEAR attached.
+fn paused_contract(context: &mut VMContextBuilder, switches: Vec<crate::pause::PauseSwitch>) -> Contract {
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.set_protocol_fee_rate(U128(50000));
+    mint_pending_to(&mut contract, context, accounts(1), 3);
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(None, None);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.nrc404_pause(switches);
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract
+}
+
+#[test]
+#[should_panic(expected = "ft transfers are paused")]
+fn test_ft_transfer_paused() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = paused_contract(&mut context, vec![PauseSwitch::FtTransfer]);
+    contract.ft_transfer(accounts(2), U128(1), None);
+}
+
+#[test]
+#[should_panic(expected = "ft transfers are paused")]
+fn test_ft_transfer_call_paused() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = paused_contract(&mut context, vec![PauseSwitch::FtTransfer]);
+    let _ = contract.ft_transfer_call(accounts(2), U128(1), None, "".to_string());
+}
+
+#[test]
+#[should_panic(expected = "ft transfers are paused")]
+fn test_claim_fees_paused() {
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = paused_contract(&mut context, vec![PauseSwitch::FtTransfer]);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.nrc404_claim_fees();
+}
+
+#[test]
+#[should_panic(expected = "nft transfers are paused")]
+fn test_nft_transfer_paused() {
+    use crate::nft_core::NonFungibleTokenCore as _;
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = paused_contract(&mut context, vec![PauseSwitch::NftTransfer]);
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    contract.nft_transfer(accounts(2), token_id, None, None);
+}
+
+#[test]
+#[should_panic(expected = "nft transfers are paused")]
+fn test_nft_transfer_call_paused() {
+    use crate::nft_core::NonFungibleTokenCore as _;
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = paused_contract(&mut context, vec![PauseSwitch::NftTransfer]);
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    let _ = contract.nft_transfer_call(accounts(2), token_id, None, None, "".to_string());
+}
+
+#[test]
+#[should_panic(expected = "nft transfers are paused")]
+fn test_nft_transfer_payout_paused() {
+    use crate::pause::PauseSwitch;
+    use crate::royalty::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = paused_contract(&mut context, vec![PauseSwitch::NftTransfer]);
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    contract.nft_transfer_payout(accounts(2), token_id, 0, None, U128(10000), 10);
+}
+
+#[test]
+#[should_panic(expected = "wrapping is paused")]
+fn test_nft_wrap_paused() {
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = paused_contract(&mut context, vec![PauseSwitch::Wrap]);
+    contract.nft_wrap(U128(0));
+}
+
+#[test]
+#[should_panic(expected = "wrapping is paused")]
+fn test_process_pending_paused() {
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = paused_contract(&mut context, vec![PauseSwitch::Wrap]);
+    contract.nrc404_process_pending(accounts(1), None);
+}
+
+#[test]
+#[should_panic(expected = "operator wrapping is paused")]
+fn test_nft_wrap_by_operator_paused() {
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = paused_contract(&mut context, vec![PauseSwitch::OperatorWrap]);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(MINT_STORAGE_COST).build());
+    contract.nft_wrap_by_operator(sample_token_metadata(), accounts(0), None);
+}
+
+#[test]
+fn test_unpause_resumes_entry_points() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::nft_core::NonFungibleTokenCore as _;
+    use crate::pause::PauseSwitch;
+    use crate::royalty::NonFungibleTokenCore as _;
+
+    let switches = vec![PauseSwitch::FtTransfer, PauseSwitch::NftTransfer, PauseSwitch::Wrap, PauseSwitch::OperatorWrap];
+    let mut context = get_context(accounts(0));
+    let mut contract = paused_contract(&mut context, switches.clone());
+    assert_eq!(contract.nrc404_paused(), switches);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.nrc404_unpause(switches);
+    assert!(contract.nrc404_paused().is_empty());
+
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.ft_transfer(accounts(2), U128(1), None);
+    let _ = contract.ft_transfer_call(accounts(2), U128(1), None, "".to_string());
+    let token_ids: Vec<String> = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+    contract.nft_transfer(accounts(2), token_ids[0].clone(), None, None);
+    contract.nft_transfer_payout(accounts(2), token_ids[1].clone(), 0, None, U128(10000), 10);
+    assert_eq!(contract.nft_supply_for_owner(accounts(2)).0, 2);
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+    let _ = contract.nft_transfer_call(accounts(1), token_ids[0].clone(), None, None, "".to_string());
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nft_unwrap(vec![token_ids[0].clone()]);
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 0);
+    contract.nft_wrap(U128(1));
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 1);
+    contract.nrc404_process_pending(accounts(1), None);
+
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    assert!(contract.nrc404_claim_fees().0 > 0);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(MINT_STORAGE_COST).build());
+    contract.nft_wrap_by_operator(sample_token_metadata(), accounts(0), None);
+}
+
+#[test]
+fn test_pause_events() {
+    use crate::pause::PauseSwitch;
+    use near_sdk::test_utils::get_logs;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_pause(vec![PauseSwitch::Wrap, PauseSwitch::FtTransfer]);
+    assert_eq!(get_logs().len(), 1);
+    assert!(get_logs()[0].contains("\"event\":\"paused\""));
+    assert!(get_logs()[0].contains("\"switches\":[\"wrap\",\"ft_transfer\"]"));
+
+    // switches that are already paused are left out, nothing is logged when none is left
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_pause(vec![PauseSwitch::Wrap]);
+    assert!(get_logs().is_empty());
+    contract.nrc404_pause(vec![PauseSwitch::Wrap, PauseSwitch::NftTransfer]);
+    assert!(get_logs()[0].contains("\"switches\":[\"nft_transfer\"]"));
+
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_unpause(vec![PauseSwitch::Wrap, PauseSwitch::OperatorWrap]);
+    assert_eq!(get_logs().len(), 1);
+    assert!(get_logs()[0].contains("\"event\":\"unpaused\""));
+    assert!(get_logs()[0].contains("\"switches\":[\"wrap\"]"));
+    assert_eq!(contract.nrc404_paused(), vec![PauseSwitch::FtTransfer, PauseSwitch::NftTransfer]);
+}
+
+#[test]
+#[should_panic(expected = "ERR_NOT_ALLOWED")]
+fn test_pause_needs_pauser() {
+    use crate::pause::PauseSwitch;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_pause(vec![PauseSwitch::Wrap]);
+}
+
+/// `units` tokens of accounts(1) revealed to level 2 in the previous block, with the context left on the owner.
+fn revealed_level_contract(context: &mut VMContextBuilder, units: u128) -> Contract {
+    testing_env!(context.build());
+    let mut contract = new_contract(two_level_metadata(), two_level_media_data());
+    mint_pending_to(&mut contract, context, accounts(1), units);
+    testing_env!(context.block_index(LEVEL_REVEAL_DELAY_BLOCKS).build());
+    contract.nrc404_reveal(accounts(1), None);
+    testing_env!(context.predecessor_account_id(accounts(0)).block_index(LEVEL_REVEAL_DELAY_BLOCKS + 1).attached_deposit(1).build());
+    contract
+}
+
+#[test]
+fn test_payout_collection_default() {
+    use crate::royalty::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    testing_env!(context.build());
+    let mut contract = new_contract(sample_contract_metadata(), sample_media_data());
+    mint_pending_to(&mut contract, &mut context, accounts(1), 1);
+    testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+    contract.set_default_royalty(HashMap::from([(accounts(2), 1000), (accounts(3), 250)]), None);
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+
+    assert_eq!(contract.nft_royalty_info(token_id.clone()), HashMap::from([(accounts(1), 8750), (accounts(2), 1000), (accounts(3), 250)]));
+    // every share is rounded down on its own
+    let payout = contract.nft_payout(token_id, U128(9999), 10).payout;
+    assert_eq!(payout, HashMap::from([(accounts(1), U128(8749)), (accounts(2), U128(999)), (accounts(3), U128(249))]));
+}
+
+#[test]
+fn test_payout_level_default() {
+    use crate::royalty::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = revealed_level_contract(&mut context, 1);
+    contract.set_default_royalty(HashMap::from([(accounts(2), 1000)]), None);
+    contract.set_default_royalty(HashMap::from([(accounts(3), 500)]), Some(2));
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+
+    let payout = contract.nft_payout(token_id.clone(), U128(10001), 10).payout;
+    assert_eq!(payout, HashMap::from([(accounts(1), U128(9500)), (accounts(3), U128(500))]));
+
+    // removing the override of the level falls back to the collection default
+    contract.set_default_royalty(HashMap::new(), Some(2));
+    let payout = contract.nft_payout(token_id, U128(10001), 10).payout;
+    assert_eq!(payout, HashMap::from([(accounts(1), U128(9000)), (accounts(2), U128(1000))]));
+}
+
+#[test]
+fn test_payout_level_rate() {
+    use crate::royalty::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = revealed_level_contract(&mut context, 1);
+    contract.set_default_royalty(HashMap::from([(accounts(2), 200), (accounts(3), 100)]), None);
+    contract.set_level_royalty_rate(2, Some(1000));
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+
+    // 1000 split 2:1 is 666 and 333, the rounding dust goes to the first recipient
+    assert_eq!(contract.get_default_royalty(Some(2)), HashMap::from([(accounts(2), 667), (accounts(3), 333)]));
+    assert_eq!(contract.get_default_royalty(Some(1)), HashMap::from([(accounts(2), 200), (accounts(3), 100)]));
+    let payout = contract.nft_payout(token_id.clone(), U128(1000), 10).payout;
+    assert_eq!(payout, HashMap::from([(accounts(1), U128(900)), (accounts(2), U128(66)), (accounts(3), U128(33))]));
+
+    // without recipients the treasury gets the whole rate
+    contract.set_default_royalty(HashMap::new(), None);
+    let payout = contract.nft_payout(token_id, U128(1000), 10).payout;
+    assert_eq!(payout, HashMap::from([(accounts(1), U128(900)), (accounts(0), U128(100))]));
+}
+
+#[test]
+fn test_payout_royalty_recipient() {
+    use crate::royalty::NonFungibleTokenCore as _;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = revealed_level_contract(&mut context, 2);
+    contract.set_default_royalty(HashMap::from([(accounts(2), 1000), (accounts(3), 500)]), None);
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(24)).build());
+    contract.storage_deposit(None, None);
+    testing_env!(context.attached_deposit(1).build());
+    contract.nrc404_set_royalty_recipient(Some(accounts(4)));
+    assert_eq!(contract.nrc404_royalty_recipient_of(accounts(2)), accounts(4));
+    // a share moved to the token owner is part of what the owner gets anyway
+    testing_env!(context.predecessor_account_id(accounts(3)).attached_deposit(1).build());
+    contract.nrc404_set_royalty_recipient(Some(accounts(1)));
+
+    let token_ids: Vec<String> = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+    let payout = contract.nft_payout(token_ids[0].clone(), U128(3333), 10).payout;
+    assert_eq!(payout, HashMap::from([(accounts(1), U128(2999)), (accounts(4), U128(333))]));
+
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    let payout = contract.nft_transfer_payout(accounts(2), token_ids[1].clone(), 0, None, U128(3333), 10).payout;
+    assert_eq!(payout, HashMap::from([(accounts(1), U128(2999)), (accounts(4), U128(333))]));
+
+    // moving the share back pays the original recipient again
+    testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+    contract.nrc404_set_royalty_recipient(None);
+    let payout = contract.nft_payout(token_ids[0].clone(), U128(3333), 10).payout;
+    assert_eq!(payout, HashMap::from([(accounts(1), U128(2999)), (accounts(2), U128(333))]));
+}
+
+#[test]
+fn test_reroll_paid_by_fee() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::RerollConfig;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = revealed_level_contract(&mut context, 2);
+    contract.set_reroll_config(Some(RerollConfig { price: U128(UNIT / 2), burn: false, cooldown_ms: 0 }));
+    let token_ids: Vec<String> = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+
+    // without FT the price is paid by burning another token, never the rerolled one
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_reroll(token_ids[1].clone());
+    let remaining: Vec<String> = contract.nft_tokens_for_owner(accounts(1), None, None).into_iter().map(|token| token.token_id).collect();
+    assert_eq!(remaining, vec![token_ids[1].clone()]);
+    assert!(!contract.nrc404_is_token_locked(token_ids[1].clone()));
+    assert_eq!(contract.token_metadata_by_id.get(&token_ids[1]).unwrap().level, PENDING_LEVEL);
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, 2 * UNIT - UNIT / 2);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(0)).0, UNIT / 2);
+    assert_eq!(contract.ft_total_supply().0, 100 * UNIT);
+
+    // the new level is revealed like a freshly minted one
+    testing_env!(context.block_index(2 * LEVEL_REVEAL_DELAY_BLOCKS + 1).build());
+    contract.nrc404_reveal(accounts(1), None);
+    assert_eq!(contract.token_metadata_by_id.get(&token_ids[1]).unwrap().level, 2);
+}
+
+#[test]
+fn test_reroll_paid_by_burn() {
+    use crate::nep141_ft_core::FungibleTokenCore;
+    use crate::RerollConfig;
+    use near_sdk::test_utils::get_logs;
+
+    let mut context = get_context(accounts(0));
+    let mut contract = revealed_level_contract(&mut context, 1);
+    contract.set_reroll_config(Some(RerollConfig { price: U128(UNIT / 2), burn: true, cooldown_ms: 0 }));
+    contract.set_protocol_fee_rate(U128(0));
+    contract.ft_transfer(accounts(1), U128(UNIT / 2), None);
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+
+    // a token locked by its owner stays locked through the reroll
+    testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+    contract.nrc404_lock_tokens(vec![token_id.clone()], true);
+    contract.nrc404_reroll(token_id.clone());
+    assert!(get_logs().iter().any(|log| log.contains("\"event\":\"ft_burn\"") && log.contains("\"memo\":\"Level reroll\"")));
+    assert!(contract.nrc404_is_token_locked(token_id.clone()));
+    assert_eq!(contract.token_metadata_by_id.get(&token_id).unwrap().level, PENDING_LEVEL);
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, UNIT);
+    assert_eq!(contract.ft_total_supply().0, 100 * UNIT - UNIT / 2);
+    assert_eq!(contract.nrc404_claimable_fees(accounts(0)).0, 0);
+}
+
+/// accounts(1) with one token rerolled at 0 ms and revealed again, with a reroll cooldown of 1000 ms.
+fn rerolled_contract(context: &mut VMContextBuilder) -> (Contract, String) {
+    use crate::RerollConfig;
+
+    let mut contract = revealed_level_contract(context, 1);
+    contract.set_reroll_config(Some(RerollConfig { price: U128(0), burn: false, cooldown_ms: 1000 }));
+    let token_id = contract.nft_tokens_for_owner(accounts(1), None, None)[0].token_id.clone();
+    testing_env!(context.predecessor_account_id(accounts(1)).block_timestamp(0).attached_deposit(1).build());
+    contract.nrc404_reroll(token_id.clone());
+    testing_env!(context.block_index(2 * LEVEL_REVEAL_DELAY_BLOCKS + 1).build());
+    contract.nrc404_reveal(accounts(1), None);
+    (contract, token_id)
+}
+
+#[test]
+#[should_panic(expected = "token is still in reroll cooldown")]
+fn test_reroll_cooldown() {
+    let mut context = get_context(accounts(0));
+    let (mut contract, token_id) = rerolled_contract(&mut context);
+    testing_env!(context.block_index(2 * LEVEL_REVEAL_DELAY_BLOCKS + 2).block_timestamp(999_000_000).attached_deposit(1).build());
+    contract.nrc404_reroll(token_id);
+}
+
+#[test]
+fn test_reroll_after_cooldown() {
+    let mut context = get_context(accounts(0));
+    let (mut contract, token_id) = rerolled_contract(&mut context);
+    testing_env!(context.block_index(2 * LEVEL_REVEAL_DELAY_BLOCKS + 2).block_timestamp(1_000_000_000).attached_deposit(1).build());
+    contract.nrc404_reroll(token_id.clone());
+    assert_eq!(contract.token_metadata_by_id.get(&token_id).unwrap().level, PENDING_LEVEL);
+}